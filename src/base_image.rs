@@ -0,0 +1,128 @@
+//! Module for staging an existing OCI base image's root filesystem so that
+//! RPMs can be layered on top of it instead of building from scratch
+//!
+//! Copyright (C) Microsoft Corporation.
+//!
+//! This program is free software: you can redistribute it and/or modify
+//! it under the terms of the GNU General Public License as published by
+//! the Free Software Foundation, either version 3 of the License, or
+//! (at your option) any later version.
+//!
+//! This program is distributed in the hope that it will be useful,
+//! but WITHOUT ANY WARRANTY; without even the implied warranty of
+//! MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//! GNU General Public License for more details.
+//!
+//! You should have received a copy of the GNU General Public License
+//! along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+use flate2::read::GzDecoder;
+use oci_spec::image::{ImageIndex, ImageManifest, MediaType};
+
+use crate::write;
+
+/// Pull `base_image` (any reference understood by `skopeo`, e.g. `docker://registry/image:tag`
+/// or `oci:path:tag`) and extract its root filesystem, layer by layer in order, into `installroot`.
+///
+/// This reuses the base image's installed rpmdb and `/etc/os-release`, since `dnf install
+/// --installroot <installroot>` run afterwards will see them already present on disk.
+///
+/// Note: only standard single-file whiteouts (`.wh.<name>`) are honored; opaque directory
+/// whiteouts (`.wh..wh..opq`) are not currently supported.
+pub(crate) fn pull_and_extract(
+    base_image: &str,
+    auth_file: Option<&Path>,
+    installroot: &Path,
+) -> Result<()> {
+    let staging_dir = tempfile::tempdir().context("Failed to create base image staging directory")?;
+    let staging_path = staging_dir.path();
+
+    write::ok("Pulling", format!("base image `{}`", base_image))?;
+    let mut skopeo = Command::new("skopeo");
+    skopeo.arg("copy");
+    if let Some(auth_file) = auth_file {
+        skopeo.arg("--authfile").arg(auth_file);
+    }
+    skopeo
+        .arg(base_image)
+        .arg(format!("oci:{}:base", staging_path.display()));
+    let status = skopeo.status().context("Failed to run `skopeo copy`")?;
+    if !status.success() {
+        bail!("Failed to pull base image `{}` with skopeo", base_image);
+    }
+
+    let index_path = staging_path.join("index.json");
+    let index = ImageIndex::from_file(&index_path)
+        .with_context(|| format!("Failed to read `{}`", index_path.display()))?;
+    let manifest_descriptor = index
+        .manifests()
+        .iter()
+        .find(|m| {
+            m.annotations()
+                .as_ref()
+                .and_then(|a| a.get("org.opencontainers.image.ref.name"))
+                .is_some_and(|t| t == "base")
+        })
+        .with_context(|| format!("No manifest tagged `base` found after pulling `{}`", base_image))?;
+
+    let manifest_digest = manifest_descriptor.digest().to_string();
+    let (manifest_algorithm, manifest_hex) = manifest_digest
+        .split_once(':')
+        .with_context(|| format!("Unexpected digest format `{}`", manifest_digest))?;
+    let manifest_path = staging_path
+        .join("blobs")
+        .join(manifest_algorithm)
+        .join(manifest_hex);
+    let manifest = ImageManifest::from_file(&manifest_path)
+        .with_context(|| format!("Failed to read `{}`", manifest_path.display()))?;
+
+    write::ok(
+        "Extracting",
+        format!("{} layer(s) from base image", manifest.layers().len()),
+    )?;
+    for layer in manifest.layers() {
+        let digest = layer.digest().to_string();
+        let (algorithm, hex) = digest
+            .split_once(':')
+            .with_context(|| format!("Unexpected digest format `{}`", digest))?;
+        let blob_path = staging_path.join("blobs").join(algorithm).join(hex);
+        let blob = fs::File::open(&blob_path)
+            .with_context(|| format!("Failed to open layer blob `{}`", blob_path.display()))?;
+
+        match layer.media_type() {
+            MediaType::ImageLayerGzip => extract_layer(GzDecoder::new(blob), installroot)?,
+            MediaType::ImageLayer => extract_layer(blob, installroot)?,
+            other => bail!("Unsupported base image layer media type `{}`", other),
+        }
+    }
+
+    Ok(())
+}
+
+fn extract_layer(reader: impl std::io::Read, installroot: &Path) -> Result<()> {
+    let mut archive = tar::Archive::new(reader);
+    archive.set_preserve_permissions(true);
+    archive.set_unpack_xattrs(true);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        if let Some(whiteout_name) = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .and_then(|n| n.strip_prefix(".wh."))
+        {
+            let target = installroot
+                .join(path.parent().unwrap_or(Path::new("")))
+                .join(whiteout_name);
+            let _ = fs::remove_file(&target);
+            let _ = fs::remove_dir_all(&target);
+            continue;
+        }
+        entry.unpack_in(installroot)?;
+    }
+    Ok(())
+}
@@ -19,6 +19,8 @@ use std::path::PathBuf;
 use clap::{Parser, Subcommand};
 use clap_verbosity_flag::Verbosity;
 
+use crate::scan::Severity;
+
 /// Main CLI struct
 #[derive(Debug, Parser)]
 #[clap(
@@ -42,6 +44,15 @@ fn label_parser(s: &str) -> Result<(String, String), String> {
     }
 }
 
+/// Supported SBOM output formats
+// CycloneDX JSON isn't implemented yet; the variant is left out rather than accepted
+// and then rejected after a full image build (see `lockfile::build::write_sbom`).
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum SbomFormat {
+    /// SPDX 2.3 JSON
+    SpdxJson,
+}
+
 /// Subcommands
 #[derive(Debug, Subcommand)]
 pub enum Command {
@@ -56,6 +67,32 @@ pub enum Command {
         /// local RPMs being present, which may be useful in dependency updating scenarios.
         #[clap(long = "from-lockfile")]
         from_lockfile: bool,
+        /// Also record source RPM checksums for every resolved package in the lock file,
+        /// for later use by `rpmoci vendor --include-sources`
+        #[clap(long = "include-sources")]
+        include_sources: bool,
+        /// Resolve a candidate lock file and print a columnar upgrade report
+        /// (current, latest available, selected version and a pinning note for each
+        /// package that would change) without writing the lock file
+        #[clap(long = "dry-run")]
+        dry_run: bool,
+        /// Allow packages to cross version requirements declared in `rpmoci.toml`
+        /// (e.g. `etcd>=3.5,<3.6`) and resolve to their true latest available version
+        #[clap(long = "breaking")]
+        breaking: bool,
+        /// Re-resolve only the named package(s) from the existing lock file, holding
+        /// every other already-locked package pinned to its current version, instead of
+        /// performing a full re-resolve that would bump everything to the latest
+        /// available version. Can be passed multiple times. Requires an existing lock
+        /// file to update selectively against.
+        #[clap(short = 'p', long = "package", conflicts_with = "from_lockfile")]
+        package: Vec<String>,
+        /// Output format for the summary of added, removed and changed packages.
+        /// `json` prints a machine-readable `Lockfile::diff` report to stdout, for CI
+        /// pipelines to gate on, instead of the human-oriented Adding/Updating/Removing
+        /// lines printed to stderr
+        #[clap(long = "format", value_enum, default_value = "text")]
+        format: UpdateFormat,
     },
     /// Build an OCI image
     Build {
@@ -63,6 +100,14 @@ pub enum Command {
         /// an error if the lock file is missing or needs to be updated
         #[clap(long = "locked")]
         locked: bool,
+        /// Equivalent to passing both `--locked` and `--offline`
+        #[clap(long = "frozen")]
+        frozen: bool,
+        /// Don't access the network: if the lock file needs to be (re-)resolved, fail
+        /// instead of resolving, and only use RPMs already present in the download
+        /// cache (see `--cache-dir`), failing if any locked package isn't cached
+        #[clap(long = "offline")]
+        offline: bool,
         #[clap(long = "image")]
         /// Path to OCI image layout
         image: String,
@@ -82,6 +127,67 @@ pub enum Command {
         /// By default, rpmoci searches for rpmoci.toml in the current directory
         #[clap(short = 'f', long = "file", default_value = "rpmoci.toml")]
         manifest_path: PathBuf,
+        /// Generate a software bill of materials for the image's installed RPMs,
+        /// written alongside the OCI image as `<image>.sbom.json`
+        #[clap(long = "sbom", value_enum)]
+        sbom: Option<SbomFormat>,
+        /// Record the installed package set, sizes and image digest of this build into
+        /// `<history-dir>/<image>-<tag>.json` (every character other than an ASCII letter
+        /// or digit replaced with `-`), diffing against the previous build's record
+        /// (if present) and printing an added/removed/changed package report
+        #[clap(long = "history-dir")]
+        history_dir: Option<PathBuf>,
+        /// Fail the build if, compared to the previous record in `--history-dir`, the total
+        /// installed size grows by more than this many bytes
+        #[clap(long = "max-size-increase", requires = "history_dir")]
+        max_size_increase: Option<u64>,
+        /// Embed the resolved lock file into the image manifest as the
+        /// `dev.rpmoci.lockfile` annotation, for later retrieval with `extract-lockfile`.
+        /// Can also be enabled via the `embed_lockfile` config option.
+        #[clap(long = "embed-lockfile")]
+        embed_lockfile: bool,
+        /// Download RPMs through a content-addressable cache keyed by checksum, so
+        /// repeated builds skip the network for packages already seen, instead of
+        /// re-downloading every RPM on every build
+        #[clap(long = "cache-dir")]
+        cache_dir: Option<PathBuf>,
+        /// The compression algorithm to use for image layers
+        #[clap(long = "compression", value_enum, default_value = "gzip")]
+        compression: crate::imager::CompressionAlgorithm,
+        /// The compression level to use for image layers. Defaults to 3 for zstd and 6
+        /// for gzip. Higher levels trade CPU and memory for a smaller layer
+        #[clap(long = "compression-level")]
+        compression_level: Option<i32>,
+        /// The zstd window log (log2 of the maximum match-window size in bytes) to use
+        /// for image layers. Not currently supported by the zstd writer in use - setting
+        /// this is rejected rather than applied
+        #[clap(long = "compression-window-log")]
+        compression_window_log: Option<u32>,
+        /// After building, push the image to this destination reference (any reference
+        /// understood by `skopeo`, e.g. `docker://registry.example.com/repo:tag`), in
+        /// addition to writing it to `--image`
+        #[clap(long = "push")]
+        push: Option<String>,
+        /// Path to the authentication file to use when pushing with `--push`
+        #[clap(long = "push-auth-file", requires = "push")]
+        push_auth_file: Option<PathBuf>,
+        /// Allow an extended attribute to be preserved in image layers. Can be a full name
+        /// (e.g. `security.capability`) or a namespace wildcard (e.g. `user.*`). Can be
+        /// passed multiple times. If not passed, every extended attribute is allowed,
+        /// preserving prior behavior
+        #[clap(long = "xattr-allow")]
+        xattr_allow: Vec<String>,
+        /// What to do with an extended attribute that isn't allowed by `--xattr-allow`
+        #[clap(long = "xattr-violation", value_enum, default_value = "strip")]
+        xattr_violation: crate::imager::XattrViolationAction,
+        /// What to do with setuid/setgid bits found on files in the rootfs
+        #[clap(long = "setid-policy", value_enum, default_value = "allow")]
+        setid_policy: crate::imager::SetidPolicy,
+        /// Record every file, directory and hardlink in image layers as owned by uid 0 /
+        /// gid 0, regardless of the owner in the installroot, so that rebuilding the same
+        /// rootfs as a different build user still produces bit-for-bit identical layers
+        #[clap(long = "remap-ids-to-root")]
+        remap_ids_to_root: bool,
     },
     /// Vendor RPM dependencies locally
     Vendor {
@@ -93,5 +199,113 @@ pub enum Command {
         /// By default, rpmoci searches for rpmoci.toml in the current directory.
         #[clap(short = 'f', long = "file", default_value = "rpmoci.toml")]
         manifest_path: PathBuf,
+        /// Also download the corresponding source RPM for every binary package into
+        /// a `sources/` subdirectory of `--out-dir`, for source-redistribution compliance
+        #[clap(long = "include-sources")]
+        include_sources: bool,
+        /// Generate a software bill of materials from the lock file's resolved packages,
+        /// written to `<out-dir>/sbom.json`. Unlike `build --sbom`, this is derived purely
+        /// from the lock file (checksums, source repositories and GPG key provenance)
+        /// since no installroot exists yet to query installed license metadata from.
+        #[clap(long = "sbom", value_enum)]
+        sbom: Option<SbomFormat>,
+        /// Download RPMs through a content-addressable cache keyed by checksum, so
+        /// repeated builds skip the network for packages already seen, instead of
+        /// re-downloading every RPM on every build
+        #[clap(long = "cache-dir")]
+        cache_dir: Option<PathBuf>,
     },
+    /// Report known vulnerabilities affecting the packages in a lock file
+    Scan {
+        /// Path to rpmoci manifest file.
+        /// By default, rpmoci searches for rpmoci.toml in the current directory.
+        #[clap(short = 'f', long = "file", default_value = "rpmoci.toml")]
+        manifest_path: PathBuf,
+        /// Directory of `updateinfo.xml`-style advisory files to scan against.
+        /// Required: rpmoci does not fetch advisories from the configured repositories.
+        #[clap(long = "advisories")]
+        advisories: PathBuf,
+        /// Only fail (exit non-zero) for advisories at or above this severity
+        #[clap(long = "severity", value_enum, default_value = "low")]
+        severity: Severity,
+        /// Output format for findings
+        #[clap(long = "format", value_enum, default_value = "text")]
+        format: ScanFormat,
+    },
+    /// Rebuild from a compatible lock file into a scratch OCI layout and check that the
+    /// resulting manifest digest is reproducible
+    Verify {
+        /// Path to rpmoci manifest file.
+        /// By default, rpmoci searches for rpmoci.toml in the current directory.
+        #[clap(short = 'f', long = "file", default_value = "rpmoci.toml")]
+        manifest_path: PathBuf,
+        /// Optionally, use RPMs from a specified directory instead of downloading them,
+        /// as with `rpmoci build --vendor-dir`
+        #[clap(long = "vendor-dir")]
+        vendor_dir: Option<PathBuf>,
+        /// The tag to give the scratch image built for verification, and the tag to look
+        /// up in `--against` if specified
+        #[clap(long = "tag", default_value = "verify")]
+        tag: String,
+        /// An existing OCI image layout to compare the rebuilt manifest digest against.
+        /// If not specified, the `expected_digest` config option is used instead.
+        #[clap(long = "against")]
+        against: Option<PathBuf>,
+    },
+    /// Materialize a self-contained local mirror of exactly the RPMs in a lock file,
+    /// laid out as a yum/dnf repository with synthesized repository metadata, so a
+    /// locked image can later be rebuilt entirely offline against it
+    Snapshot {
+        /// Path to rpmoci manifest file.
+        /// By default, rpmoci searches for rpmoci.toml in the current directory.
+        #[clap(short = 'f', long = "file", default_value = "rpmoci.toml")]
+        manifest_path: PathBuf,
+        /// The directory in which to materialize the repository snapshot. Point a
+        /// `file://` repository at this directory in `rpmoci.toml` to build offline.
+        #[clap(long = "out-dir")]
+        out_dir: PathBuf,
+    },
+    /// Extract a lock file that was embedded into an image with `build --embed-lockfile`
+    ExtractLockfile {
+        #[clap(long = "image")]
+        /// Path to OCI image layout
+        image: String,
+        #[clap(long = "tag")]
+        /// The tag of the image to extract the lock file from
+        tag: String,
+        /// Where to write the extracted lock file. Defaults to printing to stdout.
+        #[clap(short = 'o', long = "output")]
+        output: Option<PathBuf>,
+    },
+    /// Reconstruct a rootfs by extracting every layer of a previously built image,
+    /// the inverse of `build`
+    ExtractRootfs {
+        #[clap(long = "image")]
+        /// Path to OCI image layout
+        image: String,
+        #[clap(long = "tag")]
+        /// The tag of the image to extract
+        tag: String,
+        /// Directory to extract the rootfs into. Created if it doesn't already exist
+        #[clap(long = "out-dir")]
+        out_dir: PathBuf,
+    },
+}
+
+/// Output format for `rpmoci scan`
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum ScanFormat {
+    /// Human-readable text, grouped by severity
+    Text,
+    /// Machine-readable JSON, for CI consumption
+    Json,
+}
+
+/// Output format for `rpmoci update`'s summary of added, removed and changed packages
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum UpdateFormat {
+    /// Human-readable Adding/Updating/Removing lines, printed to stderr
+    Text,
+    /// A machine-readable `Lockfile::diff` report, printed as JSON to stdout
+    Json,
 }
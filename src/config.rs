@@ -14,13 +14,15 @@
 //!
 //! You should have received a copy of the GNU General Public License
 //! along with this program.  If not, see <https://www.gnu.org/licenses/>.
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
 use oci_spec::{
     image::{Arch, ConfigBuilder, ImageConfiguration, ImageConfigurationBuilder, Os},
     OciSpecError,
 };
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use url::Url;
 
 #[derive(Debug, Serialize, Default, Deserialize, Clone)]
@@ -48,6 +50,64 @@ pub(crate) struct ImageConfig {
     pub(crate) stopsignal: Option<String>,
     #[serde(default)]
     pub(crate) author: Option<String>,
+    /// Target platform architecture(s) to build the image for, as OCI/Go-style
+    /// architecture names (e.g. `amd64`, `arm64`). Defaults to a single entry for the
+    /// architecture `rpmoci` itself is running on, so existing configs keep building
+    /// exactly what they did before this field existed.
+    ///
+    /// A single entry produces one manifest, cross-building for that architecture if
+    /// it differs from the host's (see [`resolve_arch`], used to pass `--forcearch` to
+    /// `dnf` while installing). More than one entry is rejected by
+    /// [`crate::lockfile::Lockfile::build`]:
+    /// dependency resolution isn't done per architecture, so there's no way to produce a
+    /// genuinely distinct package set for each one.
+    #[serde(default = "default_arch_list")]
+    pub(crate) arch: Vec<String>,
+    /// URL of the source code repository this image is built from, populated into
+    /// the `org.opencontainers.image.source` label unless already set explicitly via
+    /// `labels` or `--label`.
+    #[serde(default)]
+    pub(crate) source: Option<Url>,
+    /// Source control revision (e.g. a git commit hash) this image is built from,
+    /// populated into `org.opencontainers.image.revision`.
+    #[serde(default)]
+    pub(crate) revision: Option<String>,
+    /// Version of the packaged software, populated into `org.opencontainers.image.version`.
+    #[serde(default)]
+    pub(crate) version: Option<String>,
+    /// Human-readable title of the image, populated into `org.opencontainers.image.title`.
+    #[serde(default)]
+    pub(crate) title: Option<String>,
+    /// Name of the distributing entity/organization, populated into
+    /// `org.opencontainers.image.vendor`.
+    #[serde(default)]
+    pub(crate) vendor: Option<String>,
+}
+
+fn default_arch_list() -> Vec<String> {
+    vec![host_oci_arch().to_string()]
+}
+
+/// The OCI/Go-style architecture name of the host `rpmoci` itself is running on.
+pub(crate) fn host_oci_arch() -> &'static str {
+    match std::env::consts::ARCH {
+        "aarch64" => "arm64",
+        _ => "amd64",
+    }
+}
+
+/// Map an OCI/Go-style architecture name (as used in [`ImageConfig::arch`]) to the
+/// corresponding `oci_spec` [`Arch`] and RPM `arch` name, the latter for `dnf
+/// --forcearch` during cross-architecture installation.
+pub(crate) fn resolve_arch(arch: &str) -> Result<(Arch, &'static str)> {
+    match arch {
+        "amd64" => Ok((Arch::Amd64, "x86_64")),
+        "arm64" => Ok((Arch::Arm64, "aarch64")),
+        other => bail!(
+            "Unsupported image architecture `{}`; supported architectures: amd64, arm64",
+            other
+        ),
+    }
 }
 
 #[derive(Debug, Serialize, Default, Deserialize, Clone)]
@@ -68,6 +128,56 @@ pub(crate) struct PackageConfig {
     /// needing to add the <distro>-release package.
     #[serde(default = "os_release_default")]
     pub(crate) os_release: bool,
+    /// SPDX license expressions that installed packages are permitted to declare.
+    /// If non-empty, any installed package whose `License` tag (split into identifiers
+    /// on its `and`/`or` operators) names an identifier that isn't in this list fails
+    /// the build. Mutually exclusive in practice with `denied_licenses`.
+    #[serde(default)]
+    pub(crate) allowed_licenses: Vec<String>,
+    /// SPDX license expressions that installed packages are forbidden from declaring.
+    /// Any installed package whose `License` tag (split into identifiers on its
+    /// `and`/`or` operators) names an identifier in this list fails the build.
+    #[serde(default)]
+    pub(crate) denied_licenses: Vec<String>,
+    /// Whether to embed the resolved lock file into the built image, as a manifest
+    /// annotation, so the image can be audited or rebuilt from without its source tree.
+    /// Can also be enabled per-build with `--embed-lockfile`.
+    #[serde(default)]
+    pub(crate) embed_lockfile: bool,
+    /// An existing OCI image to layer the resolved RPMs on top of instead of building
+    /// from scratch, as any reference understood by `skopeo copy` (e.g.
+    /// `docker://mcr.microsoft.com/cbl-mariner/base/core:2.0`).
+    #[serde(default)]
+    pub(crate) base_image: Option<String>,
+    /// Path to an authentication file (in `containers-auth.json` format) to use when
+    /// pulling `base_image` from a registry that requires authentication.
+    #[serde(default)]
+    pub(crate) base_image_auth_file: Option<std::path::PathBuf>,
+    /// A manifest digest (e.g. `sha256:...`) previously recorded for this image, used by
+    /// `rpmoci verify` to detect non-reproducible builds when `--against` isn't given.
+    #[serde(default)]
+    pub(crate) expected_digest: Option<String>,
+    /// Which package manager to resolve and download dependencies with. Defaults to
+    /// autodetecting `dnf`, falling back to `tdnf`, based on what's runnable on `PATH`.
+    #[serde(default)]
+    pub(crate) backend: Option<BackendKind>,
+    /// Whether to aggregate the `License` tag of every installed package into the
+    /// image's `org.opencontainers.image.licenses` annotation. Defaults to true;
+    /// has no effect if that annotation is already set explicitly, either via
+    /// `image.labels` or `--label`.
+    #[serde(default = "emit_licenses_default")]
+    pub(crate) emit_licenses: bool,
+}
+
+/// A package-manager backend rpmoci can resolve and download dependencies through
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum BackendKind {
+    /// Resolve and download via the `dnf` Python API
+    Dnf,
+    /// Resolve and download via the `tdnf` CLI, for bases that don't ship full dnf
+    /// (e.g. Azure Linux/Photon OS)
+    Tdnf,
 }
 
 fn docs_default() -> bool {
@@ -78,6 +188,28 @@ fn os_release_default() -> bool {
     true
 }
 
+fn emit_licenses_default() -> bool {
+    true
+}
+
+/// Returns true if `pkg_spec` pins a specific version or version range for a package
+/// (e.g. `etcd>=3.5,<3.6` or `etcd=3.5.9`), as opposed to a bare name or glob.
+///
+/// Local RPM paths/globs (ending in `.rpm`) are never considered pinned, since they
+/// aren't resolved from a repository.
+pub(crate) fn is_pinned_spec(pkg_spec: &str) -> bool {
+    !pkg_spec.ends_with(".rpm") && pkg_spec.contains(['=', '<', '>'])
+}
+
+/// The package name a pinned spec applies to, e.g. `etcd` for `etcd>=3.5,<3.6`.
+pub(crate) fn pinned_spec_name(pkg_spec: &str) -> &str {
+    pkg_spec
+        .split(['=', '<', '>'])
+        .next()
+        .unwrap_or(pkg_spec)
+        .trim()
+}
+
 /// Configuration file for rpmoci
 #[derive(Debug, Serialize, Default, Deserialize, Clone)]
 #[serde(deny_unknown_fields)]
@@ -85,6 +217,162 @@ pub struct Config {
     pub(crate) contents: PackageConfig,
     #[serde(default)]
     pub(crate) image: ImageConfig,
+    /// Path, relative to this file, to a base `rpmoci.toml` to inherit `contents` and
+    /// `image` settings from, cargo-workspace-inheritance style. See [`Config::load`]
+    /// for how a chain of these is resolved and merged.
+    #[serde(default)]
+    pub(crate) extends: Option<PathBuf>,
+}
+
+impl Config {
+    /// Returns a copy of this configuration with version constraints stripped from
+    /// every package spec, so dependency resolution is free to pick the latest
+    /// available version of each package regardless of any pins in `rpmoci.toml`.
+    ///
+    /// Used by `rpmoci update --breaking` and `--dry-run` to discover what the latest
+    /// available version of a pinned package would be.
+    #[must_use]
+    pub(crate) fn unconstrained(&self) -> Config {
+        let mut cfg = self.clone();
+        cfg.contents.packages = cfg
+            .contents
+            .packages
+            .iter()
+            .map(|spec| {
+                if is_pinned_spec(spec) {
+                    pinned_spec_name(spec).to_string()
+                } else {
+                    spec.clone()
+                }
+            })
+            .collect();
+        cfg
+    }
+
+    /// Read and fully resolve the config at `path`: follow its `extends` chain (if
+    /// any), deep-merging each base manifest's raw TOML into the child's; expand
+    /// `${VAR}` placeholders (see [`crate::template`]) in the fields listed in
+    /// [`crate::template::SUBSTITUTED_PATHS`]; then deserialize the result, so
+    /// [`deny_unknown_fields`] validation runs exactly once against the fully
+    /// resolved config rather than against each file individually.
+    ///
+    /// [`deny_unknown_fields`]: https://serde.rs/container-attrs.html#deny_unknown_fields
+    pub(crate) fn load(path: &Path) -> Result<Config> {
+        let merged = load_merged_value(path, &mut Vec::new())?;
+        let expanded = substitute_config(merged, &crate::template::substitution_vars())
+            .with_context(|| format!("Failed to expand variables in `{}`", path.display()))?;
+        Config::deserialize(expanded)
+            .with_context(|| format!("Failed to validate merged config for `{}`", path.display()))
+    }
+}
+
+/// Expand `${VAR}` placeholders (see [`crate::template`]) in the subtrees of `value`
+/// named in [`crate::template::SUBSTITUTED_PATHS`], leaving everything else untouched.
+fn substitute_config(
+    mut value: toml::Value,
+    vars: &HashMap<String, String>,
+) -> Result<toml::Value> {
+    let Some(table) = value.as_table_mut() else {
+        return Ok(value);
+    };
+    for path in crate::template::SUBSTITUTED_PATHS {
+        let Some((section, leaf)) = path.split_once('.') else {
+            continue;
+        };
+        let Some(toml::Value::Table(section)) = table.get_mut(section) else {
+            continue;
+        };
+        if let Some(leaf_value) = section.remove(leaf) {
+            section.insert(leaf.to_string(), crate::template::substitute_value(leaf_value, vars)?);
+        }
+    }
+    Ok(value)
+}
+
+/// Dotted paths (relative to the config root) of array fields that are appended -
+/// base entries first, then the child's - rather than wholly replaced when a config
+/// `extends` another. Other array fields (e.g. `image.cmd`) describe a single image's
+/// recipe rather than a list to accumulate across a base manifest, so the child's
+/// value fully replaces the base's for those instead. Tables (e.g. `image.labels`,
+/// `image.envs`) always merge key-by-key regardless of this list, since that's the
+/// natural "child overrides on collision" behaviour for a map.
+const MERGED_ARRAY_PATHS: &[&str] = &[
+    "contents.repositories",
+    "contents.packages",
+    "contents.gpgkeys",
+];
+
+/// Load `path`, recursively resolving and merging its `extends` chain, and return the
+/// fully merged config as a raw [`toml::Value`] (not yet validated against [`Config`]'s
+/// `deny_unknown_fields`, which [`Config::load`] does once the merge is complete).
+fn load_merged_value(path: &Path, seen: &mut Vec<PathBuf>) -> Result<toml::Value> {
+    let canonical = path
+        .canonicalize()
+        .with_context(|| format!("Failed to read `{}`", path.display()))?;
+    if seen.contains(&canonical) {
+        bail!(
+            "`extends` cycle detected: `{}` extends a config that (transitively) extends itself",
+            path.display()
+        );
+    }
+    seen.push(canonical);
+
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read `{}`", path.display()))?;
+    let mut value: toml::Value = toml::from_str(&contents)
+        .with_context(|| format!("Failed to parse `{}`", path.display()))?;
+
+    let extends = value
+        .get("extends")
+        .and_then(|v| v.as_str())
+        .map(String::from);
+    let Some(extends) = extends else {
+        return Ok(value);
+    };
+    let Some(table) = value.as_table_mut() else {
+        return Ok(value);
+    };
+    table.remove("extends");
+
+    let base_path = path
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .join(&extends);
+    let base_value = load_merged_value(&base_path, seen).with_context(|| {
+        format!("Failed to load `{}` extended by `{}`", extends, path.display())
+    })?;
+    Ok(merge_toml_values(base_value, value, ""))
+}
+
+/// Deep-merge `overlay`'s TOML value over `base`'s: nested tables merge key-by-key
+/// (the overlay's value wins on collision), arrays at a path listed in
+/// [`MERGED_ARRAY_PATHS`] are appended (`base` entries then `overlay`'s), and every
+/// other value in `overlay` replaces `base`'s outright.
+fn merge_toml_values(base: toml::Value, overlay: toml::Value, path: &str) -> toml::Value {
+    match (base, overlay) {
+        (toml::Value::Table(mut base), toml::Value::Table(overlay)) => {
+            for (key, overlay_value) in overlay {
+                let sub_path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                let merged = match base.remove(&key) {
+                    Some(base_value) => merge_toml_values(base_value, overlay_value, &sub_path),
+                    None => overlay_value,
+                };
+                base.insert(key, merged);
+            }
+            toml::Value::Table(base)
+        }
+        (toml::Value::Array(mut base), toml::Value::Array(overlay))
+            if MERGED_ARRAY_PATHS.contains(&path) =>
+        {
+            base.extend(overlay);
+            toml::Value::Array(base)
+        }
+        (_, overlay) => overlay,
+    }
 }
 
 /// Configuration of a yum/dnf repository
@@ -104,7 +392,15 @@ pub(crate) struct RepositoryDefinition {
     id: Option<String>,
     // The base url of the repository
     pub(crate) url: Url,
+    /// Additional mirrors to fall back to, in order, if `url` is unreachable.
+    /// These are appended to `url` in dnf's `baseurl` list, so dnf will retry
+    /// against each one in turn before failing the download.
+    #[serde(default)]
+    pub(crate) mirrors: Vec<Url>,
     /// Additional repository options.
+    ///
+    /// To configure how many times dnf retries a download before falling back
+    /// to the next mirror, set `options = { retries = "..." }`.
     #[serde(default)]
     pub(crate) options: HashMap<String, String>,
 }
@@ -140,10 +436,41 @@ impl Repository {
     }
 }
 
+/// The standard OCI annotation key for a combined license expression, used to carry
+/// the aggregated license summary built by [`crate::license::collect_license_summary`]
+/// when `contents.emit_licenses` is set. See
+/// <https://github.com/opencontainers/image-spec/blob/main/annotations.md>.
+const LICENSES_ANNOTATION: &str = "org.opencontainers.image.licenses";
+
+/// The standard OCI annotation keys auto-populated from [`ImageConfig::source`],
+/// [`ImageConfig::revision`], [`ImageConfig::version`], [`ImageConfig::title`] and
+/// [`ImageConfig::vendor`] respectively, plus `created`, all applied the same way as
+/// [`LICENSES_ANNOTATION`]: only when not already set via `labels`/`--label`. See
+/// <https://github.com/opencontainers/image-spec/blob/main/annotations.md>.
+const CREATED_ANNOTATION: &str = "org.opencontainers.image.created";
+const SOURCE_ANNOTATION: &str = "org.opencontainers.image.source";
+const REVISION_ANNOTATION: &str = "org.opencontainers.image.revision";
+const VERSION_ANNOTATION: &str = "org.opencontainers.image.version";
+const TITLE_ANNOTATION: &str = "org.opencontainers.image.title";
+const VENDOR_ANNOTATION: &str = "org.opencontainers.image.vendor";
+
 impl ImageConfig {
+    /// Build the OCI image configuration for a single architecture's manifest.
+    ///
+    /// `architecture` is one of the entries of [`Self::arch`], already resolved via
+    /// [`resolve_arch`] by the caller (once per architecture being built).
+    /// `creation_time` is used for the `created` field, so it matches
+    /// `SOURCE_DATE_EPOCH` and the layer timestamps, keeping the image reproducible.
+    /// `licenses`, if given, is only applied to [`LICENSES_ANNOTATION`] when neither
+    /// `self.labels` nor `cli_labels` already set it explicitly; the same goes for the
+    /// `created`/`source`/`revision`/`version`/`title`/`vendor` annotations derived
+    /// from `creation_time` and `self`'s own fields.
     pub(crate) fn to_oci_image_configuration(
         &self,
         cli_labels: HashMap<String, String>,
+        architecture: Arch,
+        creation_time: DateTime<Utc>,
+        licenses: Option<&str>,
     ) -> Result<ImageConfiguration, OciSpecError> {
         let ImageConfig {
             user,
@@ -156,11 +483,49 @@ impl ImageConfig {
             workingdir,
             stopsignal,
             author,
+            source,
+            revision,
+            version,
+            title,
+            vendor,
             ..
         } = &self;
         let mut builder = ConfigBuilder::default();
         let mut merged_labels = labels.clone();
         merged_labels.extend(cli_labels);
+        if let Some(licenses) = licenses {
+            merged_labels
+                .entry(LICENSES_ANNOTATION.to_string())
+                .or_insert_with(|| licenses.to_string());
+        }
+        merged_labels
+            .entry(CREATED_ANNOTATION.to_string())
+            .or_insert_with(|| creation_time.to_rfc3339());
+        if let Some(source) = source {
+            merged_labels
+                .entry(SOURCE_ANNOTATION.to_string())
+                .or_insert_with(|| source.to_string());
+        }
+        if let Some(revision) = revision {
+            merged_labels
+                .entry(REVISION_ANNOTATION.to_string())
+                .or_insert_with(|| revision.clone());
+        }
+        if let Some(version) = version {
+            merged_labels
+                .entry(VERSION_ANNOTATION.to_string())
+                .or_insert_with(|| version.clone());
+        }
+        if let Some(title) = title {
+            merged_labels
+                .entry(TITLE_ANNOTATION.to_string())
+                .or_insert_with(|| title.clone());
+        }
+        if let Some(vendor) = vendor {
+            merged_labels
+                .entry(VENDOR_ANNOTATION.to_string())
+                .or_insert_with(|| vendor.clone());
+        }
 
         // default the PATH variable to /usr/local/bin:/usr/local/sbin:/usr/bin:/usr/sbin:/bin:/sbin
         let mut envs = envs.clone();
@@ -191,9 +556,9 @@ impl ImageConfig {
 
         let mut builder = ImageConfigurationBuilder::default()
             .config(config)
-            .architecture(Arch::Amd64)
+            .architecture(architecture)
             .os(Os::Linux)
-            .created(chrono::Utc::now().to_rfc3339());
+            .created(creation_time.to_rfc3339());
         if let Some(author) = author {
             builder = builder.author(author);
         }
@@ -207,7 +572,7 @@ mod tests {
 
     use crate::config::ImageConfig;
 
-    use super::Config;
+    use super::{Config, Repository};
 
     #[test]
     fn parse_basic() {
@@ -274,13 +639,32 @@ mod tests {
         [[contents.repositories]]
         url = "https://packages.microsoft.com/cbl-mariner/2.0/prod/base/x86_64/"
         options = {includepkgs = "foo,bar"}
-        
+
         [image]
         cmd = [ "bash" ]
         "#;
         toml::from_str::<Config>(config).unwrap();
     }
 
+    #[test]
+    fn parse_repository_mirrors() {
+        let config = r#"[contents]
+        packages = ["core-packages-container"]
+        [[contents.repositories]]
+        url = "https://packages.microsoft.com/cbl-mariner/2.0/prod/base/x86_64/"
+        mirrors = ["https://mirror.example.com/cbl-mariner/2.0/prod/base/x86_64/"]
+        options = {retries = "5"}
+
+        [image]
+        cmd = [ "bash" ]
+        "#;
+        let cfg = toml::from_str::<Config>(config).unwrap();
+        let Repository::Definition(repo) = &cfg.contents.repositories[0] else {
+            panic!("expected a repository definition");
+        };
+        assert_eq!(repo.mirrors.len(), 1);
+    }
+
     #[test]
     fn path_env_defaulting() {
         let config_with_path = r#"
@@ -289,7 +673,12 @@ mod tests {
         let config: oci_spec::image::ImageConfiguration =
             toml::from_str::<ImageConfig>(config_with_path)
                 .unwrap()
-                .to_oci_image_configuration(HashMap::new())
+                .to_oci_image_configuration(
+                    HashMap::new(),
+                    oci_spec::image::Arch::Amd64,
+                    chrono::Utc::now(),
+                    None,
+                )
                 .unwrap();
         let envs = config.config().as_ref().unwrap().env().as_ref().unwrap();
         assert!(envs.iter().any(|e| e == "PATH=/usr/bin"));
@@ -301,7 +690,12 @@ mod tests {
         let config: oci_spec::image::ImageConfiguration =
             toml::from_str::<ImageConfig>(config_without_path)
                 .unwrap()
-                .to_oci_image_configuration(HashMap::new())
+                .to_oci_image_configuration(
+                    HashMap::new(),
+                    oci_spec::image::Arch::Amd64,
+                    chrono::Utc::now(),
+                    None,
+                )
                 .unwrap();
         let envs = config.config().as_ref().unwrap().env().as_ref().unwrap();
         assert!(envs
@@ -318,7 +712,12 @@ mod tests {
         // No additional labels
         let config: oci_spec::image::ImageConfiguration = toml::from_str::<ImageConfig>(config_str)
             .unwrap()
-            .to_oci_image_configuration(HashMap::new())
+            .to_oci_image_configuration(
+                HashMap::new(),
+                oci_spec::image::Arch::Amd64,
+                chrono::Utc::now(),
+                None,
+            )
             .unwrap();
         let labels = config.config().as_ref().unwrap().labels().as_ref().unwrap();
         assert_eq!(labels.get("foo.bar").unwrap(), "baz");
@@ -332,11 +731,259 @@ mod tests {
         .collect();
         let config: oci_spec::image::ImageConfiguration = toml::from_str::<ImageConfig>(config_str)
             .unwrap()
-            .to_oci_image_configuration(extra_labels)
+            .to_oci_image_configuration(
+                extra_labels,
+                oci_spec::image::Arch::Amd64,
+                chrono::Utc::now(),
+                None,
+            )
             .unwrap();
         let labels = config.config().as_ref().unwrap().labels().as_ref().unwrap();
         assert_eq!(labels.get("foo.bar").unwrap(), "qux");
         assert_eq!(labels.get("foo.baz").unwrap(), "quux");
         assert_eq!(labels.len(), 2);
     }
+
+    #[test]
+    fn licenses_annotation_defaults_but_yields_to_explicit_label() {
+        let config: oci_spec::image::ImageConfiguration =
+            toml::from_str::<ImageConfig>("")
+                .unwrap()
+                .to_oci_image_configuration(
+                    HashMap::new(),
+                    oci_spec::image::Arch::Amd64,
+                    chrono::Utc::now(),
+                    Some("MIT AND Apache-2.0"),
+                )
+                .unwrap();
+        let labels = config.config().as_ref().unwrap().labels().as_ref().unwrap();
+        assert_eq!(
+            labels.get("org.opencontainers.image.licenses").unwrap(),
+            "MIT AND Apache-2.0"
+        );
+
+        let config_str = r#"
+        labels = { "org.opencontainers.image.licenses" = "Custom-License" }
+        "#;
+        let config: oci_spec::image::ImageConfiguration = toml::from_str::<ImageConfig>(config_str)
+            .unwrap()
+            .to_oci_image_configuration(
+                HashMap::new(),
+                oci_spec::image::Arch::Amd64,
+                chrono::Utc::now(),
+                Some("MIT"),
+            )
+            .unwrap();
+        let labels = config.config().as_ref().unwrap().labels().as_ref().unwrap();
+        assert_eq!(
+            labels.get("org.opencontainers.image.licenses").unwrap(),
+            "Custom-License"
+        );
+    }
+
+    #[test]
+    fn standard_annotations_are_auto_populated_but_yield_to_explicit_labels() {
+        let config_str = r#"
+        source = "https://github.com/microsoft/rpmoci"
+        revision = "abc123"
+        version = "1.2.3"
+        title = "rpmoci"
+        vendor = "Microsoft Corporation"
+        "#;
+        let creation_time = chrono::Utc::now();
+        let config: oci_spec::image::ImageConfiguration = toml::from_str::<ImageConfig>(config_str)
+            .unwrap()
+            .to_oci_image_configuration(
+                HashMap::new(),
+                oci_spec::image::Arch::Amd64,
+                creation_time,
+                None,
+            )
+            .unwrap();
+        let labels = config.config().as_ref().unwrap().labels().as_ref().unwrap();
+        assert_eq!(
+            labels.get("org.opencontainers.image.created").unwrap(),
+            &creation_time.to_rfc3339()
+        );
+        assert_eq!(
+            labels.get("org.opencontainers.image.source").unwrap(),
+            "https://github.com/microsoft/rpmoci"
+        );
+        assert_eq!(labels.get("org.opencontainers.image.revision").unwrap(), "abc123");
+        assert_eq!(labels.get("org.opencontainers.image.version").unwrap(), "1.2.3");
+        assert_eq!(labels.get("org.opencontainers.image.title").unwrap(), "rpmoci");
+        assert_eq!(
+            labels.get("org.opencontainers.image.vendor").unwrap(),
+            "Microsoft Corporation"
+        );
+
+        let cli_labels = HashMap::from([(
+            "org.opencontainers.image.vendor".to_string(),
+            "Contoso".to_string(),
+        )]);
+        let config: oci_spec::image::ImageConfiguration = toml::from_str::<ImageConfig>(config_str)
+            .unwrap()
+            .to_oci_image_configuration(
+                cli_labels,
+                oci_spec::image::Arch::Amd64,
+                creation_time,
+                None,
+            )
+            .unwrap();
+        let labels = config.config().as_ref().unwrap().labels().as_ref().unwrap();
+        assert_eq!(
+            labels.get("org.opencontainers.image.vendor").unwrap(),
+            "Contoso"
+        );
+    }
+
+    #[test]
+    fn arch_defaults_to_host() {
+        let config: ImageConfig = toml::from_str("").unwrap();
+        assert_eq!(config.arch, vec![super::host_oci_arch().to_string()]);
+    }
+
+    #[test]
+    fn resolve_arch_maps_known_architectures() {
+        let (arch, rpm_arch) = super::resolve_arch("arm64").unwrap();
+        assert!(matches!(arch, oci_spec::image::Arch::Arm64));
+        assert_eq!(rpm_arch, "aarch64");
+
+        assert!(super::resolve_arch("riscv64").is_err());
+    }
+
+    #[test]
+    fn extends_merges_packages_repositories_and_labels() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("base.toml"),
+            r#"[contents]
+            repositories = ["https://packages.microsoft.com/cbl-mariner/2.0/prod/base/x86_64"]
+            packages = ["core-packages-container"]
+
+            [image]
+            labels = { "org.example.base" = "1" }
+            cmd = ["bash"]
+            "#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("rpmoci.toml"),
+            r#"extends = "base.toml"
+
+            [contents]
+            repositories = ["foo-base"]
+            packages = ["etcd"]
+
+            [image]
+            labels = { "org.example.child" = "2" }
+            "#,
+        )
+        .unwrap();
+
+        let cfg = Config::load(&dir.path().join("rpmoci.toml")).unwrap();
+        assert_eq!(
+            cfg.contents.packages,
+            vec!["core-packages-container", "etcd"]
+        );
+        assert_eq!(cfg.contents.repositories.len(), 2);
+        assert_eq!(cfg.image.labels.get("org.example.base").unwrap(), "1");
+        assert_eq!(cfg.image.labels.get("org.example.child").unwrap(), "2");
+        // `image.cmd` isn't one of the merged array paths, so the base's value is
+        // inherited unchanged since the child didn't set it.
+        assert_eq!(cfg.image.cmd, vec!["bash".to_string()]);
+    }
+
+    #[test]
+    fn extends_child_replaces_non_merged_array() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("base.toml"),
+            r#"[contents]
+            repositories = []
+            packages = ["core-packages-container"]
+
+            [image]
+            cmd = ["bash"]
+            "#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("rpmoci.toml"),
+            r#"extends = "base.toml"
+
+            [contents]
+            repositories = []
+            packages = []
+
+            [image]
+            cmd = ["sh", "-c", "true"]
+            "#,
+        )
+        .unwrap();
+
+        let cfg = Config::load(&dir.path().join("rpmoci.toml")).unwrap();
+        assert_eq!(cfg.image.cmd, vec!["sh", "-c", "true"]);
+    }
+
+    #[test]
+    fn extends_cycle_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("a.toml"),
+            r#"extends = "b.toml"
+            [contents]
+            packages = []
+            "#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("b.toml"),
+            r#"extends = "a.toml"
+            [contents]
+            packages = []
+            "#,
+        )
+        .unwrap();
+
+        assert!(Config::load(&dir.path().join("a.toml")).is_err());
+    }
+
+    #[test]
+    fn load_expands_builtins_and_errors_on_undefined() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("rpmoci.toml"),
+            r#"[contents]
+            repositories = []
+            packages = []
+
+            [image]
+            labels = { "org.example.arch" = "${ARCH}" }
+            cmd = ["echo", "$${ARCH}-literal"]
+            "#,
+        )
+        .unwrap();
+
+        let cfg = Config::load(&dir.path().join("rpmoci.toml")).unwrap();
+        assert_eq!(
+            cfg.image.labels.get("org.example.arch").unwrap(),
+            super::host_oci_arch()
+        );
+        // `$$` is a literal-dollar escape, so this isn't treated as a reference to ARCH.
+        assert_eq!(cfg.image.cmd[1], "${ARCH}-literal");
+
+        std::fs::write(
+            dir.path().join("undefined.toml"),
+            r#"[contents]
+            repositories = []
+            packages = []
+
+            [image]
+            labels = { "x" = "${RPMOCI_TEST_UNDEFINED_VAR}" }
+            "#,
+        )
+        .unwrap();
+        assert!(Config::load(&dir.path().join("undefined.toml")).is_err());
+    }
 }
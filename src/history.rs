@@ -0,0 +1,206 @@
+//! Module for recording build history and diffing installed package sets
+//! between rebuilds of the same image
+//!
+//! Copyright (C) Microsoft Corporation.
+//!
+//! This program is free software: you can redistribute it and/or modify
+//! it under the terms of the GNU General Public License as published by
+//! the Free Software Foundation, either version 3 of the License, or
+//! (at your option) any later version.
+//!
+//! This program is distributed in the hope that it will be useful,
+//! but WITHOUT ANY WARRANTY; without even the implied warranty of
+//! MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//! GNU General Public License for more details.
+//!
+//! You should have received a copy of the GNU General Public License
+//! along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::write;
+
+/// An installed RPM and the size it contributes to the image, as read from
+/// the installroot's rpmdb
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PackageRecord {
+    name: String,
+    evr: String,
+    arch: String,
+    /// Installed size of the package, in bytes
+    size: u64,
+}
+
+/// A deterministic record of a single build, written to `<history-dir>/<image>-<tag>.json`
+/// (every character other than an ASCII letter or digit replaced with `-`)
+#[derive(Debug, Serialize, Deserialize)]
+struct BuildRecord {
+    packages: Vec<PackageRecord>,
+    /// Sum of every package's installed size, in bytes
+    total_size: u64,
+    /// The digest of the produced image manifest
+    image_digest: String,
+}
+
+/// Record the build described by `installroot`/`image_digest` into `history_dir`, diffing it
+/// against the previous record for the same `image`/`tag` (if any) and printing the result.
+///
+/// If `max_size_increase` is set and the total installed size grew by more than that many
+/// bytes compared to the previous record, this returns an error, failing the build.
+pub(crate) fn record_and_diff(
+    history_dir: &Path,
+    image: &str,
+    tag: &str,
+    installroot: impl AsRef<Path>,
+    image_digest: &str,
+    max_size_increase: Option<u64>,
+) -> Result<()> {
+    fs::create_dir_all(history_dir)
+        .with_context(|| format!("Failed to create history directory `{}`", history_dir.display()))?;
+
+    let packages = read_installed_packages(installroot)?;
+    let total_size = packages.iter().map(|p| p.size).sum();
+    let record = BuildRecord {
+        packages,
+        total_size,
+        image_digest: image_digest.to_string(),
+    };
+
+    let record_path = history_dir.join(format!("{}.json", sanitize(&format!("{}_{}", image, tag))));
+    let previous = match fs::read_to_string(&record_path) {
+        Ok(contents) => Some(
+            serde_json::from_str::<BuildRecord>(&contents)
+                .with_context(|| format!("Invalid build history record `{}`", record_path.display()))?,
+        ),
+        Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e).context(format!("Failed to read `{}`", record_path.display())),
+    };
+
+    if let Some(previous) = &previous {
+        print_diff(previous, &record)?;
+
+        if let Some(max_size_increase) = max_size_increase {
+            let increase = record.total_size.saturating_sub(previous.total_size);
+            if increase > max_size_increase {
+                bail!(
+                    "image size grew by {} bytes, exceeding the allowed increase of {} bytes",
+                    increase,
+                    max_size_increase
+                );
+            }
+        }
+    } else {
+        write::ok(
+            "Recording",
+            format!(
+                "initial build history for `{}:{}` ({} packages, {} bytes)",
+                image, tag, record.packages.len(), record.total_size
+            ),
+        )?;
+    }
+
+    fs::write(&record_path, serde_json::to_string_pretty(&record)?)
+        .with_context(|| format!("Failed to write `{}`", record_path.display()))?;
+
+    Ok(())
+}
+
+fn print_diff(previous: &BuildRecord, current: &BuildRecord) -> Result<()> {
+    let mut old = previous
+        .packages
+        .iter()
+        .map(|p| (p.name.as_str(), p))
+        .collect::<BTreeMap<_, _>>();
+
+    let mut added = Vec::new();
+    let mut changed = Vec::new();
+    for pkg in &current.packages {
+        match old.remove(pkg.name.as_str()) {
+            Some(previous_pkg) if previous_pkg.evr != pkg.evr => {
+                changed.push((previous_pkg, pkg));
+            }
+            Some(_) => {}
+            None => added.push(pkg),
+        }
+    }
+    // Anything left in `old` was removed
+    let mut removed = old.into_values().collect::<Vec<_>>();
+    removed.sort_by(|a, b| a.name.cmp(&b.name));
+    added.sort_by(|a, b| a.name.cmp(&b.name));
+    changed.sort_by(|a, b| a.0.name.cmp(&b.0.name));
+
+    for pkg in &added {
+        write::ok("Added", format!("{} {}.{}", pkg.name, pkg.evr, pkg.arch))?;
+    }
+    for pkg in &removed {
+        write::ok("Removed", format!("{} {}.{}", pkg.name, pkg.evr, pkg.arch))?;
+    }
+    for (old_pkg, new_pkg) in &changed {
+        write::ok(
+            "Changed",
+            format!("{} {} -> {}", new_pkg.name, old_pkg.evr, new_pkg.evr),
+        )?;
+    }
+
+    let size_delta = current.total_size as i64 - previous.total_size as i64;
+    write::ok(
+        "Size",
+        format!(
+            "{} bytes ({}{} bytes)",
+            current.total_size,
+            if size_delta >= 0 { "+" } else { "" },
+            size_delta
+        ),
+    )?;
+
+    Ok(())
+}
+
+fn read_installed_packages(installroot: impl AsRef<Path>) -> Result<Vec<PackageRecord>> {
+    let output = Command::new("rpm")
+        .arg("--root")
+        .arg(installroot.as_ref())
+        .arg("-qa")
+        .arg("--qf")
+        .arg("%{NAME}\\t%{EPOCH}:%{VERSION}-%{RELEASE}\\t%{ARCH}\\t%{SIZE}\\n")
+        .output()
+        .context("Failed to run `rpm -qa` against installroot")?;
+    if !output.status.success() {
+        bail!(
+            "rpm -qa failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    let mut packages = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let fields: Vec<&str> = line.split('\t').collect();
+            PackageRecord {
+                name: fields.first().unwrap_or(&"").to_string(),
+                evr: fields
+                    .get(1)
+                    .unwrap_or(&"")
+                    .trim_start_matches("(none):")
+                    .to_string(),
+                arch: fields.get(2).unwrap_or(&"").to_string(),
+                size: fields.get(3).unwrap_or(&"0").parse().unwrap_or(0),
+            }
+        })
+        .collect::<Vec<_>>();
+    packages.sort_by(|a, b| (&a.name, &a.evr, &a.arch).cmp(&(&b.name, &b.evr, &b.arch)));
+    Ok(packages)
+}
+
+/// Sanitize a string for use as a file name
+fn sanitize(s: &str) -> String {
+    s.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect()
+}
@@ -12,23 +12,69 @@
 //!
 //! You should have received a copy of the GNU General Public License
 //! along with this program.  If not, see <https://www.gnu.org/licenses/>.
-use anyhow::{Context, Result};
+use super::{SecurityPolicy, XattrViolationAction};
+use crate::write;
+use anyhow::{bail, Context, Result};
 use std::{io::Write, os::unix::prelude::OsStrExt, path::Path};
 
 // https://mgorny.pl/articles/portability-of-tar-features.html#id25
 const PAX_SCHILY_XATTR: &[u8; 13] = b"SCHILY.xattr.";
 
+/// Whether `key` is allowed by `allowlist`, where an entry ending in `.*` matches any
+/// attribute in that namespace and any other entry must match `key` exactly. An empty
+/// allowlist allows everything.
+fn xattr_allowed(key: &std::ffi::OsStr, allowlist: &[String]) -> bool {
+    if allowlist.is_empty() {
+        return true;
+    }
+    let key = key.to_string_lossy();
+    allowlist.iter().any(|pattern| match pattern.strip_suffix(".*") {
+        Some(namespace) => {
+            key.strip_prefix(namespace)
+                .is_some_and(|rest| rest.starts_with('.'))
+        }
+        None => key == pattern.as_str(),
+    })
+}
+
 // Convert any extended attributes on the specified path to a tar PAX extension header, and add it to the tar archive
 pub(crate) fn add_pax_extension_header(
     path: impl AsRef<Path>,
     builder: &mut tar::Builder<impl Write>,
+    policy: &SecurityPolicy,
 ) -> Result<(), anyhow::Error> {
     let path = path.as_ref();
-    let xattrs = xattr::list(path)
-        .with_context(|| format!("Failed to list xattrs from `{}`", path.display()))?;
+    // `xattr::list` returns attributes in whatever order the filesystem's `listxattr` call
+    // happens to return them, which isn't guaranteed stable across rebuilds; sort them so
+    // the PAX header (and therefore the layer digest) is reproducible.
+    let mut xattrs = xattr::list(path)
+        .with_context(|| format!("Failed to list xattrs from `{}`", path.display()))?
+        .collect::<Vec<_>>();
+    xattrs.sort();
     let mut pax_header = tar::Header::new_gnu();
     let mut pax_data = Vec::new();
     for key in xattrs {
+        if !xattr_allowed(&key, &policy.xattr_allowlist) {
+            match policy.xattr_violation {
+                XattrViolationAction::Strip => continue,
+                XattrViolationAction::Warn => {
+                    write::error(
+                        "Dropped",
+                        format!(
+                            "xattr `{}` on `{}`: not in the allowed xattr namespaces",
+                            key.to_string_lossy(),
+                            path.display()
+                        ),
+                    )?;
+                    continue;
+                }
+                XattrViolationAction::Deny => bail!(
+                    "xattr `{}` on `{}` is not in the allowed xattr namespaces",
+                    key.to_string_lossy(),
+                    path.display()
+                ),
+            }
+        }
         let value = xattr::get(path, &key)
             .with_context(|| {
                 format!(
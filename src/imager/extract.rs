@@ -0,0 +1,128 @@
+//! Copyright (C) Microsoft Corporation.
+//!
+//! This program is free software: you can redistribute it and/or modify
+//! it under the terms of the GNU General Public License as published by
+//! the Free Software Foundation, either version 3 of the License, or
+//! (at your option) any later version.
+//!
+//! This program is distributed in the hope that it will be useful,
+//! but WITHOUT ANY WARRANTY; without even the implied warranty of
+//! MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//! GNU General Public License for more details.
+//!
+//! You should have received a copy of the GNU General Public License
+//! along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use std::fs;
+use std::io::Read;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use flate2::read::GzDecoder;
+use ocidir::oci_spec::image::{Descriptor, MediaType};
+
+// https://mgorny.pl/articles/portability-of-tar-features.html#id25
+const PAX_SCHILY_XATTR: &[u8] = b"SCHILY.xattr.";
+
+/// Extract the layer blob described by `descriptor` out of the OCI layout at `oci_dir`,
+/// decompressing it and unpacking its contents into `target`.
+///
+/// This is the inverse of [`super::Imager::create_image`]'s layer writing: hardlinks
+/// recorded as `EntryType::Link` entries are recreated as real hardlinks (resolved
+/// relative to `target`, the same way [`super::archive::add_pax_extension_header`]'s
+/// sibling `append_link` call wrote them), and `SCHILY.xattr.*` PAX extension headers are
+/// parsed and reapplied with `xattr::set`. Only standard single-file whiteouts
+/// (`.wh.<name>`) are honored; opaque directory whiteouts (`.wh..wh..opq`) are skipped
+/// rather than erroring, the same known limitation as [`crate::base_image::pull_and_extract`].
+pub(crate) fn extract_layer(oci_dir: &Path, descriptor: &Descriptor, target: &Path) -> Result<()> {
+    let digest = descriptor.digest().to_string();
+    let (algorithm, hex) = digest
+        .split_once(':')
+        .with_context(|| format!("Unexpected digest format `{}`", digest))?;
+    let blob_path = oci_dir.join("blobs").join(algorithm).join(hex);
+    let blob = fs::File::open(&blob_path)
+        .with_context(|| format!("Failed to open layer blob `{}`", blob_path.display()))?;
+
+    match descriptor.media_type() {
+        MediaType::ImageLayerGzip => unpack(GzDecoder::new(blob), target),
+        MediaType::ImageLayer => unpack(blob, target),
+        other => bail!(
+            "Unsupported layer media type `{}`: extracting zstd layers would require adding \
+             the `zstd` crate as a dependency",
+            other
+        ),
+    }
+}
+
+fn unpack(reader: impl Read, target: &Path) -> Result<()> {
+    let mut archive = tar::Archive::new(reader);
+    archive.set_preserve_permissions(true);
+    archive.set_preserve_mtime(true);
+    // xattrs and hardlinks are restored by hand below, so don't let tar-rs's own
+    // (cargo-feature-gated) xattr support do it a second time.
+    archive.set_unpack_xattrs(false);
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+
+        if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
+            if name == ".wh..wh..opq" {
+                // Opaque directory whiteouts aren't supported; skip rather than erroring.
+                continue;
+            }
+            if let Some(whiteout_name) = name.strip_prefix(".wh.") {
+                let victim = target
+                    .join(path.parent().unwrap_or(Path::new("")))
+                    .join(whiteout_name);
+                let _ = fs::remove_file(&victim);
+                let _ = fs::remove_dir_all(&victim);
+                continue;
+            }
+        }
+
+        if entry.header().entry_type() == tar::EntryType::Link {
+            let link_name = entry
+                .link_name()?
+                .context("hardlink entry is missing a link name")?
+                .into_owned();
+            let existing = target.join(&link_name);
+            let dest = target.join(&path);
+            if let Some(parent) = dest.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::hard_link(&existing, &dest).with_context(|| {
+                format!(
+                    "Failed to recreate hardlink `{}` -> `{}`",
+                    dest.display(),
+                    existing.display()
+                )
+            })?;
+            continue;
+        }
+
+        let xattrs = entry
+            .pax_extensions()?
+            .into_iter()
+            .flatten()
+            .filter_map(|ext| {
+                let ext = ext.ok()?;
+                let key = ext.key_bytes().strip_prefix(PAX_SCHILY_XATTR)?.to_vec();
+                Some((key, ext.value_bytes().to_vec()))
+            })
+            .collect::<Vec<_>>();
+
+        entry.unpack_in(target)?;
+        for (key, value) in xattrs {
+            let dest = target.join(&path);
+            xattr::set(&dest, std::ffi::OsStr::from_bytes(&key), &value).with_context(|| {
+                format!(
+                    "Failed to restore xattr `{}` on `{}`",
+                    String::from_utf8_lossy(&key),
+                    dest.display()
+                )
+            })?;
+        }
+    }
+    Ok(())
+}
@@ -1,7 +1,7 @@
 use std::io::Write;
 
-use super::CompressionAlgorithm;
-use anyhow::Result;
+use super::{CompressionAlgorithm, CompressionConfig};
+use anyhow::{bail, Result};
 use ocidir::{GzipLayerWriter, Layer, OciDir, ZstdLayerWriter};
 
 pub(super) enum LayerWriter<'a> {
@@ -10,19 +10,33 @@ pub(super) enum LayerWriter<'a> {
 }
 
 impl<'a> LayerWriter<'a> {
-    pub fn new(
-        ocidir: &'a OciDir,
-        compression_algorithm: CompressionAlgorithm,
-        compression_level: Option<i32>,
-    ) -> Result<Self> {
-        Ok(match compression_algorithm {
+    pub fn new(ocidir: &'a OciDir, compression: CompressionConfig) -> Result<Self> {
+        if compression.window_log.is_some()
+            && !matches!(compression.algorithm, CompressionAlgorithm::Zstd)
+        {
+            bail!(
+                "a compression window log was specified, but the compression algorithm is not zstd"
+            );
+        }
+        Ok(match compression.algorithm {
             CompressionAlgorithm::Gzip => Self::Gzip(ocidir.create_gzip_layer(
-                compression_level.map(|l| flate2::Compression::new(l.try_into().unwrap())),
-            )?),
-            CompressionAlgorithm::Zstd => Self::Zstd(ocidir.create_layer_zstd_multithread(
-                compression_level,
-                num_cpus::get().try_into().unwrap(),
+                compression.level.map(|l| flate2::Compression::new(l.try_into().unwrap())),
             )?),
+            CompressionAlgorithm::Zstd => {
+                // `ocidir`'s multithreaded zstd writer doesn't expose a window-log knob,
+                // so reject the flag here rather than silently ignoring it.
+                if compression.window_log.is_some() {
+                    bail!(
+                        "a compression window log was specified, but isn't supported by the \
+                         zstd writer used here"
+                    );
+                }
+                Self::Zstd(ocidir.create_layer_zstd_multithread(
+                    compression.level,
+                    num_cpus::get().try_into().unwrap(),
+                )?)
+            }
+            CompressionAlgorithm::ZstdChunked => bail!("zstd:chunked layers aren't supported yet"),
         })
     }
 
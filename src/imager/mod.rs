@@ -14,17 +14,23 @@
 //!
 //! You should have received a copy of the GNU General Public License
 //! along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use crate::cli::SbomFormat;
+use crate::sbom;
 use crate::write;
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use archive::add_pax_extension_header;
 use chrono::DateTime;
 use derive_builder::{Builder, UninitializedFieldError};
 use layer::LayerWriter;
 use ocidir::cap_std::fs::Dir;
-use ocidir::oci_spec::image::{Descriptor, MediaType};
+use ocidir::oci_spec::image::{
+    Arch, Descriptor, DescriptorBuilder, ImageIndex, ImageIndexBuilder, ImageManifest, MediaType,
+    Os, Platform, PlatformBuilder,
+};
 use ocidir::{new_empty_manifest, Layer, OciDir};
 use pyo3::types::{PyAnyMethods, PyModule, PyTuple};
 use pyo3::{FromPyObject, Python, ToPyObject};
+use serde::Serialize;
 use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::fs;
@@ -33,6 +39,7 @@ use std::path::{Path, PathBuf};
 use walkdir::WalkDir;
 
 mod archive;
+mod extract;
 mod layer;
 
 const CREATED_BY: &str = "Created by rpmoci";
@@ -52,6 +59,12 @@ pub struct Imager {
     /// The OCI directory where the image is being built
     #[builder(setter(custom))]
     oci_dir: OciDir,
+    /// The filesystem path of the OCI directory where the image is being built, kept
+    /// alongside `oci_dir` since the `ocidir` crate doesn't expose a way to write an
+    /// arbitrary blob or `index.json` entry, both of which
+    /// [`Self::attach_layer_sbom`] needs to publish the optional per-layer SBOM.
+    #[builder(setter(custom))]
+    oci_dir_path: PathBuf,
     /// The maximum number of layers to create.
     /// The default is 125.
     #[builder(default = "default_max_layers()")]
@@ -60,14 +73,31 @@ pub struct Imager {
     /// If not set, the current time is used.
     #[builder(default = "default_creation_time()")]
     creation_time: DateTime<chrono::Utc>,
-    /// The compression algorithm to use for the image layers.
+    /// The compression configuration to use for the image layers.
     #[builder(default)]
-    compression_algorithm: CompressionAlgorithm,
-    /// The compression level to use for the image layers.
-    ///
-    /// The default for zstd is 3, and the default for gzip is 6.
+    compression: CompressionConfig,
+    /// The security policy to apply to extended attributes and setuid/setgid bits while
+    /// walking the filesystem.
+    #[builder(default)]
+    security_policy: SecurityPolicy,
+    /// If set, every file/dir/hardlink entry is recorded with uid 0 and gid 0 in the
+    /// layer, regardless of the owner of the file in the installroot, so that a rebuild
+    /// from a different build user produces a bit-for-bit identical layer.
+    #[builder(default)]
+    remap_ids_to_root: bool,
+    /// If set, generate an SBOM listing every installed package's NEVRA, build time and
+    /// the digest of the layer its files ended up in, and attach it to the image as an
+    /// OCI 1.1 referrer artifact (a second manifest whose `subject` points at the image
+    /// manifest). Reuses the same format choice as the whole-image SBOM written by
+    /// `Lockfile::write_sbom`.
     #[builder(default)]
-    compression_level: Option<i32>,
+    sbom_format: Option<SbomFormat>,
+    /// The platform (architecture/OS, and variant where applicable) recorded on the
+    /// written image manifest. If unset, it's inferred from the dominant RPM `arch`
+    /// among the packages that ended up in the image's layers (see `infer_platform`).
+    /// Purely descriptive: it has no effect on which packages get installed.
+    #[builder(default)]
+    platform: Option<Platform>,
     /// The OCI image configuration.
     #[builder(default)]
     config: ocidir::oci_spec::image::ImageConfiguration,
@@ -85,16 +115,84 @@ pub struct Imager {
     /// The default is 5MB
     #[builder(default = "5 * 1024 * 1024")]
     rpm_size_threshold: u64,
+    /// Additional annotations to merge into the image manifest, e.g. for embedding
+    /// provenance data such as the resolved lock file.
+    #[builder(default)]
+    annotations: HashMap<String, String>,
 }
 
 /// The compression algorithm to use for the image layers.
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Debug, Default, Clone, Copy, clap::ValueEnum)]
 pub enum CompressionAlgorithm {
     /// Gzip compression
     #[default]
     Gzip,
     /// Zstandard compression
     Zstd,
+    /// Zstandard compression with a `zstd:chunked` table-of-contents. Not implemented
+    /// yet (see [`layer::LayerWriter::new`]), so hidden from the CLI for now.
+    #[value(skip)]
+    ZstdChunked,
+}
+
+/// Configuration controlling how image layers are compressed.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CompressionConfig {
+    /// The compression algorithm to use for the image layers.
+    pub algorithm: CompressionAlgorithm,
+    /// The compression level to use for the image layers.
+    ///
+    /// The default for zstd is 3, and the default for gzip is 6.
+    pub level: Option<i32>,
+    /// The zstd window log (log2 of the maximum match-window size in bytes). Must be
+    /// left unset unless `algorithm` is [`CompressionAlgorithm::Zstd`]; rejected there
+    /// too for now, since the zstd writer in use doesn't support it (see
+    /// [`layer::LayerWriter::new`]).
+    pub window_log: Option<u32>,
+}
+
+/// What to do with an extended attribute that isn't allowed by
+/// [`SecurityPolicy::xattr_allowlist`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum XattrViolationAction {
+    /// Silently drop the disallowed attribute
+    #[default]
+    Strip,
+    /// Drop the attribute, but also print a warning to stderr naming it
+    Warn,
+    /// Fail the build as soon as a disallowed attribute is found
+    Deny,
+}
+
+/// What to do with a file's setuid/setgid bits when writing it into a layer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum SetidPolicy {
+    /// Preserve setuid/setgid bits verbatim (the default, matching prior behavior)
+    #[default]
+    Allow,
+    /// Silently clear setuid/setgid bits from the mode recorded in the layer
+    Strip,
+    /// Clear the bits, but also print a warning to stderr naming each affected path
+    Warn,
+    /// Fail the build as soon as a setuid/setgid file is found
+    Deny,
+}
+
+/// Security policy consulted before each extended attribute and file mode is written into
+/// an image layer, so a packager can guarantee that only vetted capabilities and no
+/// unexpected setuid/setgid binaries end up baked into a published image, instead of
+/// trusting whatever the build root happened to produce.
+#[derive(Debug, Default, Clone)]
+pub struct SecurityPolicy {
+    /// Extended attribute name patterns (e.g. `security.capability`, `user.*`) that are
+    /// allowed to be preserved in image layers. A pattern ending in `.*` matches any
+    /// attribute in that namespace. An empty allowlist (the default) allows every
+    /// attribute, preserving prior behavior.
+    pub xattr_allowlist: Vec<String>,
+    /// What to do with an attribute that doesn't match `xattr_allowlist`.
+    pub xattr_violation: XattrViolationAction,
+    /// What to do with setuid/setgid bits found on files in the rootfs.
+    pub setid_policy: SetidPolicy,
 }
 
 #[derive(Debug)]
@@ -145,6 +243,7 @@ impl Imager {
             "Failed to create OCI image directory `{}`",
             oci_dir.display()
         ))?;
+        let oci_dir_path = std::path::absolute(oci_dir)?;
         let dir = Dir::open_ambient_dir(oci_dir, ocidir::cap_std::ambient_authority())
             .context("Failed to open image directory")?;
         let oci_dir = OciDir::ensure(dir)?;
@@ -152,6 +251,7 @@ impl Imager {
         Ok(ImagerBuilder {
             filesystem_root: Some(filesystem_root),
             oci_dir: Some(oci_dir),
+            oci_dir_path: Some(oci_dir_path),
             ..ImagerBuilder::empty()
         })
     }
@@ -160,18 +260,42 @@ impl Imager {
     ///
     /// Returns the descriptor for the image manifest.
     pub fn create_image(self) -> Result<Descriptor> {
-        // Determine most popular packages
-        let popular_packages = self.most_popular_packages()?;
-        // Create a a layer for each package
+        // Determine the most popular packages (each gets its own layer) and the tail of
+        // less popular packages that don't
+        let (popular_packages, tail_packages) = self.most_popular_packages()?;
+        // Record each popular package's NEVRA now, before `path_to_layer_map` below
+        // consumes `popular_packages`, so `finish` can pair it with its layer's digest.
+        let popular_records: Vec<Vec<PackageRecord>> = popular_packages
+            .iter()
+            .map(|py_pkg| vec![PackageRecord::from(py_pkg)])
+            .collect();
+        // Create a layer for each popular package
         let mut package_layers = self.package_layers(&popular_packages)?;
-        let path_to_layer_map = path_to_layer_map(popular_packages);
-        // Create a catchall layer for any files not in the most popular package layers
+        let mut path_to_layer_map = path_to_layer_map(popular_packages);
+
+        // Bin-pack the tail across whatever's left of the layer budget, instead of
+        // dumping it all into a single monolithic catchall layer: this way, a change to
+        // one small tail package only invalidates the one bin it happens to land in.
+        let tail_layer_offset = package_layers.len();
+        let remaining_layer_budget = self.max_layers.saturating_sub(tail_layer_offset);
+        let (mut tail_layers, tail_path_to_bin, tail_records) =
+            self.pack_tail_layers(tail_packages, remaining_layer_budget)?;
+        path_to_layer_map.extend(
+            tail_path_to_bin
+                .into_iter()
+                .map(|(path, bin)| (path, tail_layer_offset + bin)),
+        );
+        package_layers.append(&mut tail_layers);
+        let mut layer_records = popular_records;
+        layer_records.extend(tail_records);
+
+        // Create a catchall layer for any files owned by no package at all
         let mut catchall = self.create_layer(CREATED_BY, self.creation_time.timestamp())?;
 
         // Walk the filesystem and add files to the appropriate layers
         self.walk_filesystem(path_to_layer_map, &mut package_layers, &mut catchall)?;
         // Finalize the image by writing the layers to the OCI image directory
-        self.finish(package_layers, catchall)
+        self.finish(package_layers, layer_records, catchall)
     }
 
     fn package_layers<'a>(&'a self, py_pkgs: &[PyPackage]) -> Result<Vec<LayerBuilder<'a>>> {
@@ -195,14 +319,29 @@ impl Imager {
     /// - emulates tar's `--clamp-mtime` option so that any file/dir/symlink mtimes are no later than a specific value
     /// - supports hardlinks
     /// - adds files to the correct archive layer
+    ///
+    /// Hardlink bookkeeping is scoped per output layer, not global: a tar hardlink entry
+    /// can only reference a path already written earlier in the *same* tar stream, so an
+    /// inode whose hardlinks are split across package layers falls back to a full copy in
+    /// every layer but the one that saw it first.
+    ///
+    /// The filesystem is walked in sorted-by-file-name order (see `WalkDir::sort_by_file_name`
+    /// below), so both the order entries are appended in and which hardlinked path becomes the
+    /// canonical link target are already deterministic, independent of the directory iteration
+    /// order the underlying filesystem happens to return. Combined with `clamp_mtime` and
+    /// `remap_ids_to_root`, and with [`archive::add_pax_extension_header`] sorting xattr keys
+    /// before serializing them, this makes layer digests reproducible across rebuilds of the
+    /// same rootfs content, a prerequisite for build caching. `remap_ids_to_root` only applies
+    /// to the headers built here (files, dirs and hardlinks): symlink ownership is preserved
+    /// verbatim, since tar-rs's `append_path_with_name` doesn't offer a way to override it.
     fn walk_filesystem<'a>(
         &self,
         path_to_layer_map: HashMap<PathBuf, usize>,
         package_layers: &mut [LayerBuilder<'a>],
         catchall: &mut LayerBuilder<'a>,
     ) -> Result<()> {
-        // Map (dev, inode) -> path for hardlinks
-        let mut hardlinks: HashMap<(u64, u64), PathBuf> = HashMap::new();
+        // Map layer key (`None` for the catchall layer) -> (dev, inode) -> path, for hardlinks
+        let mut hardlinks: HashMap<Option<usize>, HashMap<(u64, u64), PathBuf>> = HashMap::new();
 
         for entry in WalkDir::new(&self.filesystem_root)
             .follow_links(false)
@@ -224,8 +363,9 @@ impl Imager {
             }
 
             // Determine which builder to use
-            let wrapped_builder = match path_to_layer_map.get(&rel_path) {
-                Some(i) => &mut package_layers[*i],
+            let layer_key = path_to_layer_map.get(&rel_path).copied();
+            let wrapped_builder = match layer_key {
+                Some(i) => &mut package_layers[i],
                 None => catchall,
             };
             // Mark the builder as used so that we know to add it to the OCI image
@@ -240,19 +380,28 @@ impl Imager {
                     let mtime = filetime::FileTime::from_unix_time(clamp_mtime, 0);
                     filetime::set_symlink_file_times(entry.path(), mtime, mtime)?;
                 }
-                add_pax_extension_header(entry.path(), builder)?;
+                add_pax_extension_header(entry.path(), builder, &self.security_policy)?;
                 builder.append_path_with_name(entry.path(), rel_path)?;
             } else if entry.file_type().is_file() || entry.file_type().is_dir() {
-                add_pax_extension_header(entry.path(), builder)?;
+                add_pax_extension_header(entry.path(), builder, &self.security_policy)?;
 
                 // If this is a hardlink, add a link header instead of the file
                 // if this isn't the first time we've seen this inode
                 if meta.nlink() > 1 {
-                    match hardlinks.entry((meta.dev(), meta.ino())) {
+                    match hardlinks
+                        .entry(layer_key)
+                        .or_default()
+                        .entry((meta.dev(), meta.ino()))
+                    {
                         Entry::Occupied(e) => {
                             // Add link header and continue to next entry
                             let mut header = tar::Header::new_gnu();
                             header.set_metadata(&meta);
+                            apply_setid_policy(&self.security_policy, &meta, &rel_path, &mut header)?;
+                            if self.remap_ids_to_root {
+                                header.set_uid(0);
+                                header.set_gid(0);
+                            }
                             if meta.mtime() > clamp_mtime {
                                 header.set_mtime(clamp_mtime as u64);
                             }
@@ -271,6 +420,11 @@ impl Imager {
                 let mut header = tar::Header::new_gnu();
                 header.set_size(meta.len());
                 header.set_metadata(&meta);
+                apply_setid_policy(&self.security_policy, &meta, &rel_path, &mut header)?;
+                if self.remap_ids_to_root {
+                    header.set_uid(0);
+                    header.set_gid(0);
+                }
                 if meta.mtime() > clamp_mtime {
                     header.set_mtime(clamp_mtime as u64);
                 }
@@ -290,10 +444,11 @@ impl Imager {
     }
 
     /// Finalize the image by writing the layers to the OCI image directory
-    /// and updating the given manifest and image configuration
+    /// and updating the given manifest and image configuration, tagging it with `self.tag`.
     fn finish<'a>(
         &self,
         package_layers: Vec<LayerBuilder<'a>>,
+        layer_records: Vec<Vec<PackageRecord>>,
         catchall: LayerBuilder<'a>,
     ) -> Result<Descriptor> {
         write::ok("Writing", "image layers")?;
@@ -301,10 +456,28 @@ impl Imager {
         let mut manifest = self.manifest.clone();
         let mut config = self.config.clone();
 
+        if !self.annotations.is_empty() {
+            let mut merged = manifest.annotations().clone().unwrap_or_default();
+            merged.extend(self.annotations.clone());
+            manifest.set_annotations(Some(merged));
+        }
+
+        // Packages whose layer made it into the final image, paired with the digest of
+        // the layer that ended up containing them. Only collected when a per-layer SBOM
+        // was actually requested, since it's otherwise wasted bookkeeping.
+        let mut sbom_entries: Vec<sbom::LayerSbomEntry> = Vec::new();
+
+        // Computed from `layer_records` before it's consumed by the `zip` below.
+        let platform = self
+            .platform
+            .clone()
+            .unwrap_or_else(|| infer_platform(&layer_records));
+
         package_layers
             .into_iter()
-            .filter(|b| b.used)
-            .try_for_each(|builder| {
+            .zip(layer_records)
+            .filter(|(b, _)| b.used)
+            .try_for_each(|(builder, records)| {
                 let (layer, created_by) = builder.finish()?;
                 self.oci_dir.push_layer_full(
                     &mut manifest,
@@ -314,6 +487,21 @@ impl Imager {
                     &created_by,
                     self.creation_time,
                 );
+                if self.sbom_format.is_some() {
+                    // `push_layer_full` just appended this layer's descriptor, so it's
+                    // the last entry in the manifest's layer list.
+                    let digest = manifest
+                        .layers()
+                        .last()
+                        .expect("a layer was just pushed above")
+                        .digest()
+                        .to_string();
+                    sbom_entries.extend(
+                        records
+                            .into_iter()
+                            .map(|record| record.into_sbom_entry(digest.clone())),
+                    );
+                }
                 Result::<_, anyhow::Error>::Ok(())
             })?;
 
@@ -330,12 +518,95 @@ impl Imager {
         }
 
         write::ok("Writing", "image manifest and config")?;
-        Ok(self.oci_dir.insert_manifest_and_config(
+        let image_descriptor = self.oci_dir.insert_manifest_and_config(
             manifest,
             config,
-            Some(&self.tag),
-            ocidir::oci_spec::image::Platform::default(),
-        )?)
+            Some(self.tag.as_str()),
+            platform,
+        )?;
+
+        if let Some(format) = self.sbom_format {
+            self.attach_layer_sbom(format, &sbom_entries, &image_descriptor)?;
+        }
+
+        Ok(image_descriptor)
+    }
+
+    /// Generate a per-layer SBOM in `format` and publish it as an OCI 1.1 referrer: a
+    /// separate artifact manifest, with `subject` set to `image_descriptor`, appended to
+    /// `index.json` alongside (but not tagging over) the image manifest itself.
+    ///
+    /// `oci_spec::image::ImageManifest` doesn't model the 1.1 `subject` field, so the
+    /// artifact manifest is hand-rolled the same way `sbom`'s SPDX types are, and written
+    /// directly to the layout directory rather than through `OciDir`, which doesn't expose
+    /// a way to write an arbitrary blob or manifest.
+    fn attach_layer_sbom(
+        &self,
+        format: SbomFormat,
+        entries: &[sbom::LayerSbomEntry],
+        image_descriptor: &Descriptor,
+    ) -> Result<()> {
+        let contents = match format {
+            SbomFormat::SpdxJson => {
+                sbom::generate_layer_spdx(entries, &self.tag, self.creation_time)?
+            }
+        };
+
+        write::ok("Writing", "per-layer SBOM as an OCI referrer")?;
+
+        // The OCI 1.1 convention for an artifact manifest with no meaningful config: an
+        // empty JSON object, referenced by a fixed, well-known media type.
+        let empty_config = self.write_blob(b"{}", "application/vnd.oci.empty.v1+json")?;
+        let sbom_layer = self.write_blob(&contents, "application/spdx+json")?;
+        let artifact_manifest = ReferrerManifest {
+            schema_version: 2,
+            media_type: "application/vnd.oci.image.manifest.v1+json".to_string(),
+            artifact_type: "application/spdx+json".to_string(),
+            config: empty_config,
+            layers: vec![sbom_layer],
+            subject: ReferrerDescriptor {
+                media_type: "application/vnd.oci.image.manifest.v1+json".to_string(),
+                digest: image_descriptor.digest().to_string(),
+                size: image_descriptor.size(),
+            },
+        };
+        let manifest_bytes = serde_json::to_vec_pretty(&artifact_manifest)
+            .context("Failed to serialize per-layer SBOM referrer manifest")?;
+        let manifest_descriptor = self.write_blob(
+            &manifest_bytes,
+            "application/vnd.oci.image.manifest.v1+json",
+        )?;
+
+        let index_path = self.oci_dir_path.join("index.json");
+        let index = ImageIndex::from_file(&index_path)
+            .with_context(|| format!("Failed to read `{}`", index_path.display()))?;
+        let mut manifests = index.manifests().clone();
+        manifests.push(
+            DescriptorBuilder::default()
+                .digest(manifest_descriptor.digest)
+                .media_type(MediaType::ImageManifest)
+                .size(manifest_descriptor.size)
+                .build()?,
+        );
+        // Rebuilt with a bare `schemaVersion`/`manifests`; any other fields of the
+        // existing `index.json` are not carried over.
+        let updated_index = ImageIndexBuilder::default()
+            .schema_version(2u32)
+            .manifests(manifests)
+            .build()?;
+        serde_json::to_writer(
+            fs::File::create(&index_path)
+                .with_context(|| format!("Failed to open `{}`", index_path.display()))?,
+            &updated_index,
+        )
+        .with_context(|| format!("Failed to write `{}`", index_path.display()))?;
+
+        Ok(())
+    }
+
+    /// Write `bytes` into the OCI layout's blob store under its own sha256 digest.
+    fn write_blob(&self, bytes: &[u8], media_type: &str) -> Result<ReferrerDescriptor> {
+        write_blob_at(&self.oci_dir_path, bytes, media_type)
     }
 
     fn create_layer(
@@ -343,11 +614,7 @@ impl Imager {
         created_by: impl Into<String>,
         clamp_mtime: i64,
     ) -> Result<LayerBuilder> {
-        let mut inner = tar::Builder::new(LayerWriter::new(
-            &self.oci_dir,
-            self.compression_algorithm,
-            self.compression_level,
-        )?);
+        let mut inner = tar::Builder::new(LayerWriter::new(&self.oci_dir, self.compression)?);
         inner.follow_symlinks(false);
         Ok(LayerBuilder {
             inner,
@@ -357,7 +624,10 @@ impl Imager {
         })
     }
 
-    fn most_popular_packages(&self) -> Result<Vec<PyPackage>> {
+    /// Returns `(popular_packages, tail_packages)`: the packages popular enough to get
+    /// their own dedicated layer, and every other installed package, which
+    /// [`Self::pack_tail_layers`] bin-packs across the remaining layer budget.
+    fn most_popular_packages(&self) -> Result<(Vec<PyPackage>, Vec<PyPackage>)> {
         Python::with_gil(|py| {
             // Resolve is a compiled in python module for resolving dependencies
             let _nix_closure_graph = PyModule::from_code_bound(
@@ -375,6 +645,9 @@ impl Imager {
                     self.rpm_size_threshold.to_object(py),
                 ],
             );
+            // `most_popular_packages` returns a 2-tuple of (popular, tail) package lists;
+            // it already has to enumerate every installed package to rank them, so handing
+            // back the unpopular remainder alongside the popular subset is free.
             Ok::<_, anyhow::Error>(
                 graph
                     .getattr("most_popular_packages")?
@@ -384,6 +657,187 @@ impl Imager {
         })
         .context("Failed to determine layer graph")
     }
+
+    /// Bin-pack `tail_packages` (packages that didn't make the popular cut) across
+    /// `bin_count` layers, using a deterministic largest-package-first greedy placement:
+    /// sort packages by descending installed size (ties broken by name), then place each
+    /// one into whichever bin currently holds the least total size so far (ties broken by
+    /// bin index). This is the standard largest-first greedy (LPT) heuristic for balanced
+    /// bin packing, borrowed from the same idea as ostree-rs-ext's package-keyed chunking.
+    ///
+    /// Returns the non-empty bin layers, densely indexed from 0; a map from each packed
+    /// file's path to the index (into the returned `Vec`) of the layer it landed in; and,
+    /// for each returned layer, the NEVRA of every package packed into it.
+    fn pack_tail_layers<'a>(
+        &'a self,
+        mut tail_packages: Vec<PyPackage>,
+        bin_count: usize,
+    ) -> Result<(
+        Vec<LayerBuilder<'a>>,
+        HashMap<PathBuf, usize>,
+        Vec<Vec<PackageRecord>>,
+    )> {
+        if bin_count == 0 || tail_packages.is_empty() {
+            return Ok((Vec::new(), HashMap::new(), Vec::new()));
+        }
+
+        tail_packages.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.name.cmp(&b.name)));
+
+        let mut bin_sizes = vec![0u64; bin_count];
+        let mut bin_packages: Vec<Vec<PyPackage>> = (0..bin_count).map(|_| Vec::new()).collect();
+        for pkg in tail_packages {
+            let (bin, _) = bin_sizes
+                .iter()
+                .enumerate()
+                .min_by(|(a_idx, a_size), (b_idx, b_size)| {
+                    a_size.cmp(b_size).then_with(|| a_idx.cmp(b_idx))
+                })
+                .expect("bin_count > 0");
+            bin_sizes[bin] += pkg.size;
+            bin_packages[bin].push(pkg);
+        }
+
+        let mut path_to_bin = HashMap::new();
+        let mut layers = Vec::new();
+        let mut layer_records = Vec::new();
+        for pkgs in bin_packages {
+            if pkgs.is_empty() {
+                continue;
+            }
+            let clamp_mtime = pkgs
+                .iter()
+                .map(|pkg| pkg.buildtime)
+                .max()
+                .expect("pkgs is non-empty");
+            let names = pkgs
+                .iter()
+                .map(|pkg| format!("{}-{}.{}", pkg.name, pkg.evr, pkg.arch))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let layer_idx = layers.len();
+            layers.push(
+                self.create_layer(format!("{} for packages {}", CREATED_BY, names), clamp_mtime)?,
+            );
+            layer_records.push(pkgs.iter().map(PackageRecord::from).collect());
+            for pkg in pkgs {
+                for file in pkg.files {
+                    let rel_path = file
+                        .strip_prefix("/")
+                        .map(|p| p.to_path_buf())
+                        .unwrap_or(file);
+                    path_to_bin.insert(rel_path, layer_idx);
+                }
+            }
+        }
+        Ok((layers, path_to_bin, layer_records))
+    }
+}
+
+/// Infer the OCI platform (`architecture`/`os`, and `variant` where applicable) to record
+/// for an image, from the dominant RPM `arch` among the packages that ended up in its
+/// layers (ties broken by whichever arch is encountered first). Mirrors the architecture
+/// mapping tools like mkosi apply when translating a distro arch into its OCI equivalent.
+fn infer_platform(layer_records: &[Vec<PackageRecord>]) -> Platform {
+    let mut counts: Vec<(&str, usize)> = Vec::new();
+    for record in layer_records.iter().flatten() {
+        match counts.iter_mut().find(|(arch, _)| *arch == record.arch) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((record.arch.as_str(), 1)),
+        }
+    }
+    // `max_by_key` returns the last maximum, so search in reverse to prefer the first-seen arch
+    let dominant_arch = counts
+        .iter()
+        .rev()
+        .max_by_key(|(_, count)| *count)
+        .map(|(arch, _)| *arch)
+        .unwrap_or("x86_64");
+
+    let (architecture, variant) = match dominant_arch {
+        "aarch64" => (Arch::Arm64, None),
+        "armv7hl" | "armhfp" => (Arch::Arm, Some("v7")),
+        "x86_64_v2" => (Arch::Amd64, Some("v2")),
+        "x86_64_v3" => (Arch::Amd64, Some("v3")),
+        "x86_64_v4" => (Arch::Amd64, Some("v4")),
+        _ => (Arch::Amd64, None),
+    };
+
+    let mut builder = PlatformBuilder::default()
+        .architecture(architecture)
+        .os(Os::Linux);
+    if let Some(variant) = variant {
+        builder = builder.variant(variant);
+    }
+    builder
+        .build()
+        .expect("architecture and os are always set")
+}
+
+/// Write `bytes` into the OCI layout's blob store at `oci_dir_path` under its own sha256
+/// digest.
+fn write_blob_at(
+    oci_dir_path: &Path,
+    bytes: &[u8],
+    media_type: &str,
+) -> Result<ReferrerDescriptor> {
+    let digest = format!("sha256:{}", hex::encode(openssl::sha::sha256(bytes)));
+    let blob_path = oci_dir_path
+        .join("blobs")
+        .join("sha256")
+        .join(digest.rsplit_once(':').expect("digest always has a colon").1);
+    fs::write(&blob_path, bytes)
+        .with_context(|| format!("Failed to write blob `{}`", blob_path.display()))?;
+    Ok(ReferrerDescriptor {
+        media_type: media_type.to_string(),
+        digest,
+        size: bytes.len().try_into()?,
+    })
+}
+
+/// Reconstruct a rootfs by extracting every layer of the manifest tagged `tag` in the
+/// OCI image layout directory at `image`, in order, into `target`.
+///
+/// This is the inverse of [`Imager::create_image`]: it's useful for round-trip testing,
+/// layer inspection, and rebuilding a rootfs from a previously produced image.
+pub(crate) fn extract_rootfs(image: impl AsRef<Path>, tag: &str, target: &Path) -> Result<()> {
+    let image = image.as_ref();
+    let index_path = image.join("index.json");
+    let index = ImageIndex::from_file(&index_path)
+        .with_context(|| format!("Failed to read `{}`", index_path.display()))?;
+
+    let manifest_descriptor = index
+        .manifests()
+        .iter()
+        .find(|m| {
+            m.annotations()
+                .as_ref()
+                .and_then(|a| a.get("org.opencontainers.image.ref.name"))
+                .is_some_and(|t| t == tag)
+        })
+        .with_context(|| format!("No manifest tagged `{}` found in `{}`", tag, image.display()))?;
+
+    let digest = manifest_descriptor.digest().to_string();
+    let (algorithm, hex) = digest
+        .split_once(':')
+        .with_context(|| format!("Unexpected digest format `{}`", digest))?;
+    let manifest_path = image.join("blobs").join(algorithm).join(hex);
+    let manifest = ImageManifest::from_file(&manifest_path)
+        .with_context(|| format!("Failed to read `{}`", manifest_path.display()))?;
+
+    write::ok(
+        "Extracting",
+        format!(
+            "{} layer(s) from `{}:{}`",
+            manifest.layers().len(),
+            image.display(),
+            tag
+        ),
+    )?;
+    fs::create_dir_all(target)?;
+    for layer in manifest.layers() {
+        extract::extract_layer(image, layer, target)?;
+    }
+    Ok(())
 }
 
 /// A struct for extracting package information from a hawkey.Package
@@ -394,6 +848,70 @@ struct PyPackage {
     arch: String,
     files: Vec<PathBuf>,
     buildtime: i64,
+    /// Total installed size of the package's files, in bytes (hawkey's `installsize`),
+    /// used to balance tail packages across bin-packed layers in
+    /// [`Imager::pack_tail_layers`].
+    size: u64,
+}
+
+/// A package's NEVRA and build time, carried alongside each [`LayerBuilder`] so that
+/// [`Imager::finish`] can pair it with the digest of the layer it ended up in, once that's
+/// known, for the optional per-layer SBOM.
+#[derive(Debug, Clone)]
+struct PackageRecord {
+    name: String,
+    evr: String,
+    arch: String,
+    buildtime: i64,
+}
+
+impl From<&PyPackage> for PackageRecord {
+    fn from(py_pkg: &PyPackage) -> Self {
+        Self {
+            name: py_pkg.name.clone(),
+            evr: py_pkg.evr.clone(),
+            arch: py_pkg.arch.clone(),
+            buildtime: py_pkg.buildtime,
+        }
+    }
+}
+
+impl PackageRecord {
+    /// Pair this record with the digest of the layer its files were written into.
+    fn into_sbom_entry(self, layer_digest: String) -> sbom::LayerSbomEntry {
+        sbom::LayerSbomEntry {
+            name: self.name,
+            evr: self.evr,
+            arch: self.arch,
+            buildtime: self.buildtime,
+            layer_digest,
+        }
+    }
+}
+
+/// The subset of the OCI 1.1 artifact manifest spec needed to publish a referrer (e.g. an
+/// SBOM) pointing back at the image manifest it describes. Hand-rolled, since
+/// `oci_spec::image::ImageManifest` doesn't model the `subject` field the 1.1 spec added.
+#[derive(Debug, Serialize)]
+struct ReferrerManifest {
+    #[serde(rename = "schemaVersion")]
+    schema_version: u32,
+    #[serde(rename = "mediaType")]
+    media_type: String,
+    #[serde(rename = "artifactType")]
+    artifact_type: String,
+    config: ReferrerDescriptor,
+    layers: Vec<ReferrerDescriptor>,
+    subject: ReferrerDescriptor,
+}
+
+/// A descriptor within a [`ReferrerManifest`].
+#[derive(Debug, Serialize)]
+struct ReferrerDescriptor {
+    #[serde(rename = "mediaType")]
+    media_type: String,
+    digest: String,
+    size: i64,
 }
 
 struct LayerBuilder<'a> {
@@ -413,6 +931,45 @@ impl<'a> LayerBuilder<'a> {
     }
 }
 
+// setuid/setgid bits within a `st_mode` value, per POSIX
+const S_ISUID: u32 = 0o4000;
+const S_ISGID: u32 = 0o2000;
+
+/// Apply `policy.setid_policy` to `header`, which must already have had its mode set from
+/// `meta` (e.g. via `header.set_metadata`).
+fn apply_setid_policy(
+    policy: &SecurityPolicy,
+    meta: &std::fs::Metadata,
+    rel_path: &Path,
+    header: &mut tar::Header,
+) -> Result<()> {
+    if meta.mode() & (S_ISUID | S_ISGID) == 0 {
+        return Ok(());
+    }
+    match policy.setid_policy {
+        SetidPolicy::Allow => Ok(()),
+        SetidPolicy::Strip => {
+            header.set_mode(meta.mode() & !(S_ISUID | S_ISGID));
+            Ok(())
+        }
+        SetidPolicy::Warn => {
+            header.set_mode(meta.mode() & !(S_ISUID | S_ISGID));
+            write::error(
+                "Stripped",
+                format!(
+                    "setuid/setgid bit from `{}` per the configured security policy",
+                    rel_path.display()
+                ),
+            )?;
+            Ok(())
+        }
+        SetidPolicy::Deny => bail!(
+            "`{}` has a setuid/setgid bit, which is denied by the configured security policy",
+            rel_path.display()
+        ),
+    }
+}
+
 fn path_to_layer_map(py_pkgs: Vec<PyPackage>) -> HashMap<PathBuf, usize> {
     // Map paths to the index of the layer they belong to
     let mut path_to_layer_idx = HashMap::new();
@@ -22,11 +22,21 @@ use std::{
 };
 
 use anyhow::{bail, Context};
+mod base_image;
 pub mod cli;
 pub mod config;
+mod history;
+mod imager;
+mod license;
 pub mod lockfile;
 mod oci;
+mod provenance;
+mod push;
+mod sbom;
+pub mod scan;
 mod sha256_writer;
+mod template;
+mod verify;
 pub mod write;
 use anyhow::Result;
 use cli::Command;
@@ -39,9 +49,7 @@ fn load_config_and_lock_file(
     config_file: impl AsRef<Path>,
 ) -> Result<(Config, PathBuf, Result<Option<Lockfile>>)> {
     let config_file = config_file.as_ref();
-    let contents = std::fs::read_to_string(config_file)
-        .context(format!("Failed to read `{}`", config_file.display()))?;
-    let cfg: Config = toml::from_str(&contents)?;
+    let cfg = Config::load(config_file)?;
     let mut lockfile_path = PathBuf::from(config_file);
     lockfile_path.set_extension("lock");
     Ok((cfg, lockfile_path.clone(), read_lockfile(&lockfile_path)))
@@ -61,10 +69,29 @@ pub fn main(command: Command) -> anyhow::Result<()> {
         Command::Update {
             manifest_path,
             from_lockfile,
+            include_sources,
+            dry_run,
+            breaking,
+            package,
+            format,
         } => {
             let (cfg, lockfile_path, existing_lockfile) = load_config_and_lock_file(manifest_path)?;
+            // Unless --breaking was passed, version requirements declared on packages in
+            // rpmoci.toml (e.g. `etcd>=3.5,<3.6`) are respected during resolution.
+            let resolve_cfg = if breaking { cfg.unconstrained() } else { cfg };
+            let cfg = resolve_cfg;
 
-            let lockfile = if let Ok(Some(lockfile)) = &existing_lockfile {
+            let mut lockfile = if !package.is_empty() {
+                let existing = existing_lockfile
+                    .as_ref()
+                    .ok()
+                    .and_then(Option::as_ref)
+                    .context(
+                        "selectively updating with --package requires an existing lock file; \
+                         run `rpmoci update` first",
+                    )?;
+                existing.resolve_update(&cfg, &package)?
+            } else if let Ok(Some(lockfile)) = &existing_lockfile {
                 if lockfile.is_compatible_excluding_local_rpms(&cfg) && from_lockfile {
                     lockfile.resolve_from_previous(&cfg)?
                 } else {
@@ -80,17 +107,55 @@ pub fn main(command: Command) -> anyhow::Result<()> {
                 Lockfile::resolve_from_config(&cfg)?
             };
 
-            lockfile.print_updates(existing_lockfile.unwrap_or_default().as_ref())?;
-            lockfile.write_to_file(lockfile_path)?;
+            if include_sources {
+                let tmp_dir = tempfile::tempdir()?;
+                let source_packages = lockfile.download_source_rpms(tmp_dir.path())?;
+                lockfile.set_source_packages(source_packages);
+            }
+
+            let previous = existing_lockfile.unwrap_or_default();
+            if dry_run {
+                let latest = Lockfile::resolve_from_config(&cfg.unconstrained())?;
+                lockfile.print_dry_run_report(previous.as_ref(), &latest, &cfg)?;
+            } else {
+                match format {
+                    cli::UpdateFormat::Text => lockfile.print_updates(previous.as_ref())?,
+                    cli::UpdateFormat::Json => {
+                        let diff = lockfile.diff(previous.as_ref());
+                        println!("{}", serde_json::to_string_pretty(&diff)?)
+                    }
+                }
+                lockfile.write_to_file(lockfile_path)?;
+            }
         }
         Command::Build {
             locked,
+            frozen,
+            offline,
             image,
             tag,
             vendor_dir,
             manifest_path,
             label,
+            sbom,
+            history_dir,
+            max_size_increase,
+            embed_lockfile,
+            cache_dir,
+            compression,
+            compression_level,
+            compression_window_log,
+            push,
+            push_auth_file,
+            xattr_allow,
+            xattr_violation,
+            setid_policy,
+            remap_ids_to_root,
         } => {
+            // `--frozen` is equivalent to passing both `--locked` and `--offline`.
+            let locked = locked || frozen;
+            let offline = offline || frozen;
+
             let now = Instant::now();
             let mut changed = false;
             let (cfg, lockfile_path, existing_lockfile) = load_config_and_lock_file(manifest_path)?;
@@ -110,6 +175,11 @@ pub fn main(command: Command) -> anyhow::Result<()> {
                     if lockfile.is_compatible_including_local_rpms(&cfg)? {
                         // Compatible lockfile, use it
                         lockfile
+                    } else if offline {
+                        bail!(format!(
+                            "the lock file {} needs to be updated, but --offline/--frozen was passed to prevent resolving over the network",
+                            lockfile_path.display()
+                        ));
                     } else {
                         // Incompatible lockfile, update it
                         changed = true;
@@ -124,6 +194,12 @@ pub fn main(command: Command) -> anyhow::Result<()> {
                     }
                 }
                 (Err(err), false) => {
+                    if offline {
+                        return Err(err.context(format!(
+                            "failed to parse existing lock file {}, and --offline/--frozen was passed to prevent resolving a new one over the network",
+                            lockfile_path.display()
+                        )));
+                    }
                     write::error(
                         "Warning",
                         format!(
@@ -150,6 +226,12 @@ pub fn main(command: Command) -> anyhow::Result<()> {
                 ))
                 }
                 (Ok(None), false) => {
+                    if offline {
+                        bail!(format!(
+                            "the lock file {} is missing and needs to be generated, but --offline/--frozen was passed to prevent resolving over the network",
+                            lockfile_path.display()
+                        ));
+                    }
                     changed = true;
                     Lockfile::resolve_from_config(&cfg)?
                 }
@@ -159,12 +241,29 @@ pub fn main(command: Command) -> anyhow::Result<()> {
                 lockfile.write_to_file(lockfile_path)?;
             }
 
-            lockfile.build(
+            let manifest_descriptor = lockfile.build(
                 &cfg,
                 &image,
                 &tag,
                 vendor_dir.as_deref(),
                 label.into_iter().collect(),
+                sbom,
+                history_dir.as_deref(),
+                max_size_increase,
+                embed_lockfile || cfg.contents.embed_lockfile,
+                cache_dir.as_deref(),
+                offline,
+                imager::CompressionConfig {
+                    algorithm: compression,
+                    level: compression_level,
+                    window_log: compression_window_log,
+                },
+                imager::SecurityPolicy {
+                    xattr_allowlist: xattr_allow,
+                    xattr_violation,
+                    setid_policy,
+                },
+                remap_ids_to_root,
             )?;
             let elapsed_time = now.elapsed();
             write::ok(
@@ -176,19 +275,60 @@ pub fn main(command: Command) -> anyhow::Result<()> {
                     elapsed_time.as_secs_f32()
                 ),
             )?;
+
+            if let Some(push_dest) = push {
+                push::push(
+                    &image,
+                    &tag,
+                    &push_dest,
+                    push_auth_file.as_deref(),
+                    &manifest_descriptor.digest().to_string(),
+                )?;
+            }
         }
         Command::Vendor {
             out_dir,
             manifest_path,
+            include_sources,
+            sbom,
+            cache_dir,
         } => {
             fs::create_dir_all(&out_dir).context("Failed to create vendor directory")?;
-            let (cfg, _lockfile_path, existing_lockfile) =
+            let (cfg, lockfile_path, existing_lockfile) =
                 load_config_and_lock_file(manifest_path)?;
 
-            if let Ok(Some(lockfile)) = existing_lockfile {
+            if let Ok(Some(mut lockfile)) = existing_lockfile {
                 if lockfile.is_compatible_excluding_local_rpms(&cfg) {
-                    lockfile.download_rpms(&cfg, &out_dir)?;
-                    lockfile.check_gpg_keys(&out_dir)?;
+                    match &cache_dir {
+                        Some(cache_dir) => {
+                            lockfile.download_rpms_native(&cfg, &out_dir, Some(cache_dir), false)?
+                        }
+                        None => lockfile.download_rpms(&cfg, &out_dir)?,
+                    }
+                    lockfile.verify(&out_dir)?;
+                    if include_sources {
+                        let source_packages =
+                            lockfile.download_source_rpms(&out_dir.join("sources"))?;
+                        lockfile.set_source_packages(source_packages);
+                        lockfile.write_to_file(lockfile_path)?;
+                    }
+                    if let Some(format) = sbom {
+                        let contents = match format {
+                            cli::SbomFormat::SpdxJson => {
+                                sbom::generate_spdx_from_lockfile(
+                                    &lockfile,
+                                    &cfg,
+                                    out_dir.to_string_lossy().as_ref(),
+                                    chrono::Utc::now(),
+                                )?
+                            }
+                        };
+                        let sbom_path = out_dir.join("sbom.json");
+                        fs::write(&sbom_path, contents).with_context(|| {
+                            format!("Failed to write SBOM to `{}`", sbom_path.display())
+                        })?;
+                        write::ok("Generated", format!("SBOM at `{}`", sbom_path.display()))?;
+                    }
                 } else {
                     bail!(
                         "Lockfile out of date. `vendor` can only be run with a compatible lockfile"
@@ -200,6 +340,99 @@ pub fn main(command: Command) -> anyhow::Result<()> {
                 )
             }
         }
+        Command::Scan {
+            manifest_path,
+            advisories,
+            severity,
+            format,
+        } => {
+            let (_cfg, _lockfile_path, existing_lockfile) =
+                load_config_and_lock_file(manifest_path)?;
+            let lockfile = existing_lockfile?.ok_or_else(|| {
+                anyhow::anyhow!("No lock file found. Run `rpmoci update` first")
+            })?;
+
+            let advisories = scan::load_advisories(advisories)?;
+            let findings = scan::scan(&lockfile, &advisories);
+
+            match format {
+                cli::ScanFormat::Text => scan::print_findings(&findings)?,
+                cli::ScanFormat::Json => {
+                    println!("{}", serde_json::to_string_pretty(&findings)?)
+                }
+            }
+
+            if findings.iter().any(|f| f.severity >= severity) {
+                std::process::exit(1);
+            }
+        }
+        Command::Verify {
+            manifest_path,
+            vendor_dir,
+            tag,
+            against,
+        } => {
+            let (cfg, lockfile_path, existing_lockfile) = load_config_and_lock_file(manifest_path)?;
+            let lockfile = existing_lockfile?.ok_or_else(|| {
+                anyhow::anyhow!(
+                    "No lock file found at `{}`. Run `rpmoci update` first",
+                    lockfile_path.display()
+                )
+            })?;
+            if !lockfile.is_compatible_excluding_local_rpms(&cfg) {
+                bail!(
+                    "the lock file {} is not up-to-date. Run `rpmoci update` first",
+                    lockfile_path.display()
+                );
+            }
+            verify::verify(
+                &lockfile,
+                &cfg,
+                vendor_dir.as_deref(),
+                &tag,
+                against.as_deref(),
+                cfg.contents.expected_digest.as_deref(),
+            )?;
+        }
+        Command::Snapshot {
+            manifest_path,
+            out_dir,
+        } => {
+            fs::create_dir_all(&out_dir).context("Failed to create snapshot directory")?;
+            let (cfg, lockfile_path, existing_lockfile) =
+                load_config_and_lock_file(manifest_path)?;
+            let lockfile = existing_lockfile?.ok_or_else(|| {
+                anyhow::anyhow!(
+                    "No lock file found at `{}`. Run `rpmoci update` first",
+                    lockfile_path.display()
+                )
+            })?;
+            if !lockfile.is_compatible_excluding_local_rpms(&cfg) {
+                bail!(
+                    "the lock file {} is not up-to-date. Run `rpmoci update` first",
+                    lockfile_path.display()
+                );
+            }
+            lockfile.snapshot_repo(&cfg, &out_dir)?;
+        }
+        Command::ExtractLockfile { image, tag, output } => {
+            let contents = provenance::extract_lockfile(&image, &tag)?;
+            match output {
+                Some(path) => {
+                    fs::write(&path, &contents)
+                        .with_context(|| format!("Failed to write `{}`", path.display()))?;
+                    write::ok("Extracted", format!("lock file to `{}`", path.display()))?;
+                }
+                None => print!("{}", contents),
+            }
+        }
+        Command::ExtractRootfs {
+            image,
+            tag,
+            out_dir,
+        } => {
+            imager::extract_rootfs(&image, &tag, &out_dir)?;
+        }
     }
     Ok(())
 }
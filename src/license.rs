@@ -0,0 +1,188 @@
+//! Module for enforcing and recording license compliance of installed packages
+//!
+//! Copyright (C) Microsoft Corporation.
+//!
+//! This program is free software: you can redistribute it and/or modify
+//! it under the terms of the GNU General Public License as published by
+//! the Free Software Foundation, either version 3 of the License, or
+//! (at your option) any later version.
+//!
+//! This program is distributed in the hope that it will be useful,
+//! but WITHOUT ANY WARRANTY; without even the implied warranty of
+//! MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//! GNU General Public License for more details.
+//!
+//! You should have received a copy of the GNU General Public License
+//! along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+
+use crate::config::PackageConfig;
+use crate::write;
+
+/// Name, EVR and declared SPDX-ish license expression of an installed package
+struct InstalledLicense {
+    name: String,
+    evr: String,
+    license: String,
+}
+
+/// Split an RPM `License` tag into its individual license identifiers on its
+/// `and`/`or` operators, nested ones included (matched as whole whitespace-separated tokens, case
+/// insensitively, so e.g. `GPL-2.0-or-later` isn't mistaken for an `or` operator),
+/// trimming surrounding parentheses from what's left.
+fn split_spdx_license(license: &str) -> Vec<String> {
+    license
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .split(|tok: &&str| matches!(tok.to_ascii_lowercase().as_str(), "and" | "or"))
+        .map(|group| {
+            group
+                .join(" ")
+                .trim_matches(|c: char| c == '(' || c == ')' || c.is_whitespace())
+                .to_string()
+        })
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// Gather the normalized, deduplicated set of SPDX-ish license identifiers declared
+/// by every package installed in `installroot`, for `org.opencontainers.image.licenses`
+/// (joining with ` AND ` is the closest fit without a full SPDX expression
+/// parser/validator). Returns `None` if no package declared a usable license.
+pub(crate) fn collect_license_summary(installroot: impl AsRef<Path>) -> Result<Option<String>> {
+    let installed = query_installed_licenses(installroot)?;
+    let licenses: std::collections::BTreeSet<String> = installed
+        .iter()
+        .flat_map(|pkg| split_spdx_license(&pkg.license))
+        .filter(|license| license != "NOASSERTION")
+        .collect();
+    if licenses.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(licenses.into_iter().collect::<Vec<_>>().join(" AND ")))
+}
+
+fn query_installed_licenses(installroot: impl AsRef<Path>) -> Result<Vec<InstalledLicense>> {
+    let output = Command::new("rpm")
+        .arg("--root")
+        .arg(installroot.as_ref())
+        .arg("-qa")
+        .arg("--qf")
+        .arg("%{NAME}\\t%{EVR}\\t%{LICENSE}\\n")
+        .output()
+        .context("Failed to run `rpm -qa` to query package licenses")?;
+    if !output.status.success() {
+        bail!(
+            "rpm -qa failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let fields: Vec<&str> = line.split('\t').collect();
+            InstalledLicense {
+                name: fields.first().unwrap_or(&"").to_string(),
+                evr: fields.get(1).unwrap_or(&"").to_string(),
+                license: fields.get(2).unwrap_or(&"NOASSERTION").to_string(),
+            }
+        })
+        .collect())
+}
+
+/// License files (files marked `%license` in the RPM spec) shipped by a package
+fn query_license_files(installroot: impl AsRef<Path>, name: &str) -> Result<Vec<String>> {
+    let output = Command::new("rpm")
+        .arg("--root")
+        .arg(installroot.as_ref())
+        .arg("--licensefiles")
+        .arg(name)
+        .output()
+        .context("Failed to run `rpm --licensefiles`")?;
+    if !output.status.success() {
+        // Not every rpm build supports --licensefiles; treat failure as "no files recorded"
+        return Ok(Vec::new());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+/// Enforce the `allowed_licenses`/`denied_licenses` policy against the packages
+/// installed in `installroot`, and write a `{image}.licenses.json` manifest alongside
+/// the built image (like the SBOM - see `lockfile::build::write_sbom`) mapping each
+/// installed NEVRA to its declared license expression and license files.
+///
+/// Each package's `License` tag is split on every `and`/`or` operator (see
+/// [`split_spdx_license`]) before being checked against the policy.
+///
+/// Fails the build if a policy is configured and any installed package violates it.
+pub(crate) fn enforce_policy_and_collect(
+    installroot: impl AsRef<Path>,
+    image: &str,
+    cfg: &PackageConfig,
+) -> Result<()> {
+    let installroot = installroot.as_ref();
+    let installed = query_installed_licenses(installroot)?;
+
+    for pkg in &installed {
+        for license in split_spdx_license(&pkg.license) {
+            if !cfg.denied_licenses.is_empty() && cfg.denied_licenses.contains(&license) {
+                write::error(
+                    "Error",
+                    format!(
+                        "package `{}-{}` has denied license `{}`",
+                        pkg.name, pkg.evr, license
+                    ),
+                )?;
+                bail!(
+                    "package `{}-{}` declares denied license `{}`",
+                    pkg.name,
+                    pkg.evr,
+                    license
+                );
+            }
+            if !cfg.allowed_licenses.is_empty() && !cfg.allowed_licenses.contains(&license) {
+                write::error(
+                    "Error",
+                    format!(
+                        "package `{}-{}` has license `{}` which is not in the allowed list",
+                        pkg.name, pkg.evr, license
+                    ),
+                )?;
+                bail!(
+                    "package `{}-{}` declares license `{}` which is not allowed",
+                    pkg.name,
+                    pkg.evr,
+                    license
+                );
+            }
+        }
+    }
+
+    // Build a manifest of NEVRA -> license expression -> license file paths
+    let mut manifest: BTreeMap<String, BTreeMap<String, Vec<String>>> = BTreeMap::new();
+    for pkg in &installed {
+        let nevra = format!("{}-{}", pkg.name, pkg.evr);
+        let files = query_license_files(installroot, &pkg.name)?;
+        manifest
+            .entry(nevra)
+            .or_default()
+            .insert(pkg.license.clone(), files);
+    }
+
+    // Written alongside the image, not into `installroot`, which is packed into image
+    // layers - a manifest shipped inside the rootfs would have no way to be cleaned up.
+    let manifest_path = format!("{}.licenses.json", image);
+    std::fs::write(&manifest_path, serde_json::to_vec_pretty(&manifest)?)
+        .with_context(|| format!("Failed to write license manifest `{}`", manifest_path))?;
+
+    Ok(())
+}
@@ -18,17 +18,31 @@ use std::path::Path;
 use std::{fs, process::Command};
 
 use super::Lockfile;
-use crate::config::Config;
+use crate::base_image;
+use crate::cli::SbomFormat;
+use crate::config::{resolve_arch, Config};
+use crate::history;
 use crate::imager;
+use crate::provenance;
+use crate::sbom;
 use crate::write;
 use anyhow::{bail, Context, Result};
 use chrono::DateTime;
 use glob::glob;
+use ocidir::oci_spec::image::{Descriptor, Os, PlatformBuilder};
 use rusqlite::Connection;
 use tempfile::TempDir;
 
 impl Lockfile {
-    /// Build a container image from a lockfile
+    /// Build a container image from a lockfile, for the single architecture in
+    /// `cfg.image.arch`.
+    ///
+    /// More than one architecture is rejected: dependency resolution isn't done per
+    /// architecture, so there's no way to install a genuinely distinct package set for
+    /// each one - see [`crate::config::ImageConfig::arch`].
+    ///
+    /// Returns the descriptor of the image manifest that was written, e.g. for a caller
+    /// that wants to push exactly the image just built (see [`crate::push::push`]).
     pub fn build(
         &self,
         cfg: &Config,
@@ -36,57 +50,195 @@ impl Lockfile {
         tag: &str,
         vendor_dir: Option<&Path>,
         labels: HashMap<String, String>,
-    ) -> Result<()> {
+        sbom_format: Option<SbomFormat>,
+        history_dir: Option<&Path>,
+        max_size_increase: Option<u64>,
+        embed_lockfile: bool,
+        cache_dir: Option<&Path>,
+        offline: bool,
+        compression: imager::CompressionConfig,
+        security_policy: imager::SecurityPolicy,
+        remap_ids_to_root: bool,
+    ) -> Result<Descriptor> {
+        if cfg.image.arch.is_empty() {
+            bail!("`image.arch` must list at least one architecture to build");
+        }
+        if cfg.image.arch.len() > 1 {
+            bail!(
+                "building for multiple architectures ({}) isn't supported yet: dependency \
+                 resolution isn't done per architecture, so every entry would install the \
+                 same resolved package set under a mislabelled architecture",
+                cfg.image.arch.join(", ")
+            );
+        }
         let creation_time = creation_time()?;
-        let installroot = TempDir::new()?; // This needs to outlive the image builder below.
-        let image_config = cfg
-            .image
-            .to_oci_image_configuration(labels, creation_time)?;
-
-        // Create the image writer early to ensure the image directory is created successfully
-        let image_builder = imager::Imager::with_paths(installroot.path(), image)?
-            .creation_time(creation_time)
-            .config(image_config)
-            .tag(tag)
-            .build();
-
-        if let Some(vendor_dir) = vendor_dir {
-            // Use vendored RPMs rather than downloading
-            self.create_installroot(installroot.path(), vendor_dir, false, cfg, &creation_time)
-        } else {
-            // No vendoring - download RPMs
-            let tmp_rpm_dir = TempDir::new()?;
-            self.create_installroot(
-                installroot.path(),
-                tmp_rpm_dir.path(),
-                true,
-                cfg,
-                &creation_time,
-            )
+
+        // One installroot + image builder per requested architecture. The installroot
+        // needs to outlive the image builder below, so it's kept alongside it rather
+        // than dropped at the end of the loop.
+        let mut installroots = Vec::with_capacity(cfg.image.arch.len());
+        let mut image_builders = Vec::with_capacity(cfg.image.arch.len());
+
+        for arch in &cfg.image.arch {
+            let (oci_arch, rpm_arch) = resolve_arch(arch)?;
+            let installroot = TempDir::new()?;
+
+            if let Some(vendor_dir) = vendor_dir {
+                // Use vendored RPMs rather than downloading
+                self.create_installroot(
+                    installroot.path(),
+                    vendor_dir,
+                    false,
+                    cfg,
+                    image,
+                    &creation_time,
+                    cache_dir,
+                    offline,
+                    rpm_arch,
+                )
+            } else {
+                // No vendoring - download RPMs
+                let tmp_rpm_dir = TempDir::new()?;
+                self.create_installroot(
+                    installroot.path(),
+                    tmp_rpm_dir.path(),
+                    true,
+                    cfg,
+                    image,
+                    &creation_time,
+                    cache_dir,
+                    offline,
+                    rpm_arch,
+                )
+            }
+            .with_context(|| format!("Failed to create installroot for `{}`", arch))?;
+
+            // Needs the installroot populated above, so the installed packages'
+            // `License` tags are available to aggregate.
+            let licenses = if cfg.contents.emit_licenses {
+                crate::license::collect_license_summary(installroot.path())?
+            } else {
+                None
+            };
+            let image_config = cfg.image.to_oci_image_configuration(
+                labels.clone(),
+                oci_arch,
+                creation_time,
+                licenses.as_deref(),
+            )?;
+            let platform = PlatformBuilder::default()
+                .architecture(oci_arch)
+                .os(Os::Linux)
+                .build()
+                .context("Failed to build image platform")?;
+
+            // Create the image writer early to ensure the image directory is created successfully
+            let mut image_builder = imager::Imager::with_paths(installroot.path(), image)?
+                .creation_time(creation_time)
+                .config(image_config)
+                .compression(compression)
+                .security_policy(security_policy)
+                .remap_ids_to_root(remap_ids_to_root)
+                // Reuse the same format choice as the whole-image SBOM written below: when
+                // set, also attach a per-layer SBOM to the image as an OCI referrer.
+                .sbom_format(sbom_format)
+                .platform(platform)
+                .tag(tag);
+            if embed_lockfile {
+                let (key, value) = provenance::lockfile_annotation(self)?;
+                image_builder = image_builder.annotations(HashMap::from([(key, value)]));
+            }
+            let image_builder = image_builder.build();
+
+            installroots.push(installroot);
+            image_builders.push(image_builder);
         }
-        .context("Failed to create installroot")?;
 
-        image_builder.create_image()?;
+        if let Some(format) = sbom_format {
+            self.write_sbom(installroots[0].path(), image, format, creation_time)?;
+        }
+
+        let [image_builder] = <[imager::Imager; 1]>::try_from(image_builders)
+            .expect("exactly one architecture, enforced above");
+        let manifest_descriptor = image_builder.create_image()?;
+
+        if let Some(history_dir) = history_dir {
+            history::record_and_diff(
+                history_dir,
+                image,
+                tag,
+                installroots[0].path(),
+                &manifest_descriptor.digest().to_string(),
+                max_size_increase,
+            )?;
+        }
 
+        Ok(manifest_descriptor)
+    }
+
+    /// Generate an SBOM describing the RPMs installed in `installroot` and write it
+    /// alongside the image as `<image>.sbom.json`
+    fn write_sbom(
+        &self,
+        installroot: &Path,
+        image: &str,
+        format: SbomFormat,
+        creation_time: DateTime<chrono::Utc>,
+    ) -> Result<()> {
+        let contents = match format {
+            SbomFormat::SpdxJson => sbom::generate_spdx(installroot, image, creation_time)?,
+        };
+        let sbom_path = format!("{}.sbom.json", image);
+        fs::write(&sbom_path, contents)
+            .with_context(|| format!("Failed to write SBOM to `{}`", sbom_path))?;
+        write::ok("Generated", format!("SBOM at `{}`", sbom_path))?;
         Ok(())
     }
 
+    /// `rpm_arch` is the RPM architecture to install for (e.g. `x86_64`, `aarch64`, see
+    /// [`resolve_arch`]), passed to `dnf install` as `--forcearch` so an architecture
+    /// other than the host's own can be installed into `installroot`.
     fn create_installroot(
         &self,
         installroot: &Path,
         rpm_dir: &Path,
         download_rpms: bool,
         cfg: &Config,
+        image: &str,
         creation_time: &DateTime<chrono::Utc>,
+        cache_dir: Option<&Path>,
+        offline: bool,
+        rpm_arch: &str,
     ) -> Result<(), anyhow::Error> {
+        if let Some(base_image_ref) = &cfg.contents.base_image {
+            base_image::pull_and_extract(
+                base_image_ref,
+                cfg.contents.base_image_auth_file.as_deref(),
+                installroot,
+            )
+            .context("Failed to stage base image")?;
+        }
+
         if download_rpms {
-            self.download_rpms(cfg, rpm_dir)?;
+            if offline {
+                // Offline mode only ever uses the cache, so force the native
+                // cache-backed download path even if `--cache-dir` wasn't given.
+                self.download_rpms_native(cfg, rpm_dir, cache_dir, true)?;
+            } else {
+                match cache_dir {
+                    Some(cache_dir) => {
+                        self.download_rpms_native(cfg, rpm_dir, Some(cache_dir), false)?
+                    }
+                    None => self.download_rpms(cfg, rpm_dir)?,
+                }
+            }
         }
-        self.check_gpg_keys(rpm_dir)?;
+        self.verify(rpm_dir)?;
         let mut dnf_install = Command::new("dnf");
         dnf_install
             .env("SOURCE_DATE_EPOCH", creation_time.timestamp().to_string())
             .arg("--disablerepo=*")
+            .arg(format!("--forcearch={}", rpm_arch))
             .arg("--installroot")
             .arg(installroot)
             .arg("install")
@@ -129,6 +281,11 @@ impl Lockfile {
         }
         write::ok("Installed", "packages successfully")?;
 
+        if !cfg.contents.allowed_licenses.is_empty() || !cfg.contents.denied_licenses.is_empty() {
+            crate::license::enforce_policy_and_collect(installroot, image, &cfg.contents)
+                .context("License policy check failed")?;
+        }
+
         // Remove unnecessary installation artifacts from the rootfs if present
         let _ = fs::remove_dir_all(installroot.join("var/log"));
         let _ = fs::remove_dir_all(installroot.join("var/cache"));
@@ -12,60 +12,319 @@
 //!
 //! You should have received a copy of the GNU General Public License
 //! along with this program.  If not, see <https://www.gnu.org/licenses/>.
-use std::collections::HashSet;
+use std::collections::{BTreeSet, HashSet};
 use std::ffi::OsStr;
 use std::fs::{self, File};
-use std::path::Path;
+use std::io::Read;
+use std::path::{Path, PathBuf};
 use std::{io::Write, process::Command};
 
 use anyhow::{bail, Context, Result};
+use openssl::hash::{Hasher, MessageDigest};
+use openssl::sha::Sha256;
 use pyo3::ffi::c_str;
 use pyo3::prelude::*;
 use pyo3::types::PyTuple;
-use tempfile::{tempdir, TempDir};
+use rpm::signature::pgp;
+use tempfile::tempdir;
+#[cfg(feature = "legacy-rpmkeys")]
+use tempfile::TempDir;
+use url::Url;
 
 use super::resolve::setup_base;
-use super::Lockfile;
-use crate::config::Config;
+use super::{Algorithm, Checksum, Lockfile, Package, SourcePackage};
+use crate::config::{Config, Repository};
 use crate::write;
 
 impl Lockfile {
-    /// Download RPMs to a given directory
-    pub fn download_rpms(&self, cfg: &Config, dir: &Path) -> Result<()> {
-        let repositories = &cfg.contents.repositories;
-
-        Python::with_gil(|py| {
-            let base = setup_base(py, repositories, &cfg.contents.gpgkeys)?;
-            let download = PyModule::from_code(
-                py,
-                c_str!(include_str!("download.py")),
-                c_str!("resolve"),
-                c_str!("resolve"),
-            )?;
+    /// Download RPMs to `dir`, reusing a content-addressable cache keyed by each
+    /// package's lock file checksum so repeated builds and CI runs skip the network
+    /// entirely for packages already seen.
+    ///
+    /// Cache lookups (which involve re-hashing potentially large cached files) are
+    /// parallelized across packages. Any packages not already cached are downloaded in
+    /// one batch via [`Self::download_rpms`], verified against their recorded checksum,
+    /// and promoted into the cache for next time. On a digest mismatch, bails with the
+    /// package name and both digests rather than silently accepting a corrupt download.
+    ///
+    /// The cache directory defaults to `$XDG_CACHE_HOME/rpmoci` (or `~/.cache/rpmoci`)
+    /// when `cache_dir` is `None`.
+    ///
+    /// When `offline` is set, a cache miss is a hard error instead of triggering a
+    /// network download, for `rpmoci build --offline`/`--frozen`.
+    pub fn download_rpms_native(
+        &self,
+        cfg: &Config,
+        dir: &Path,
+        cache_dir: Option<&Path>,
+        offline: bool,
+    ) -> Result<()> {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create `{}`", dir.display()))?;
+        let cache_dir = match cache_dir {
+            Some(cache_dir) => cache_dir.to_path_buf(),
+            None => default_cache_dir()?,
+        };
+        fs::create_dir_all(&cache_dir)
+            .with_context(|| format!("Failed to create cache `{}`", cache_dir.display()))?;
 
-            let packages = self
-                .packages
+        let packages: Vec<&Package> = self.packages.iter().collect();
+        let hits = std::thread::scope(|scope| {
+            let handles = packages
                 .iter()
-                .map(|p| (p.name.clone(), p.evr.clone(), p.checksum.checksum.clone()))
+                .copied()
+                .map(|pkg| scope.spawn(|| try_link_from_cache(pkg, &cache_dir, dir)))
                 .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("cache lookup thread panicked"))
+                .collect::<Result<Vec<bool>>>()
+        })?;
+
+        let total = packages.len();
+        let misses = packages
+            .into_iter()
+            .zip(hits)
+            .filter_map(|(pkg, hit)| if hit { None } else { Some(pkg.clone()) })
+            .collect::<Vec<_>>();
+
+        if misses.is_empty() {
+            write::ok(
+                "Cached",
+                format!(
+                    "all {} package(s) found in `{}`",
+                    total,
+                    cache_dir.display()
+                ),
+            )?;
+            return Ok(());
+        }
+        if offline {
+            bail!(
+                "{} package(s) not found in the local cache `{}`, and --offline/--frozen was \
+                 passed to prevent network access: {}",
+                misses.len(),
+                cache_dir.display(),
+                misses
+                    .iter()
+                    .map(|pkg| format!("{}-{}", pkg.name, pkg.evr))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
 
-            let args = PyTuple::new(
-                py,
-                [
-                    base.as_any(),
-                    packages.into_pyobject(py)?.as_any(),
-                    dir.into_pyobject(py)?.as_any(),
-                ],
+        write::ok(
+            "Downloading",
+            format!("{} package(s) not found in cache", misses.len()),
+        )?;
+
+        let staging = tempdir().context("Failed to create download staging directory")?;
+        let mut miss_lockfile = self.clone();
+        miss_lockfile.packages = misses.into_iter().collect();
+        miss_lockfile.download_rpms(cfg, staging.path())?;
+
+        for pkg in &miss_lockfile.packages {
+            let downloaded = find_downloaded_rpm(staging.path(), pkg)?;
+            let digest = hash_file(&downloaded, &pkg.checksum.algorithm)?;
+            if digest != pkg.checksum.checksum {
+                bail!(
+                    "digest mismatch downloading `{}-{}`: lock file has `{}`, downloaded file has `{}`",
+                    pkg.name,
+                    pkg.evr,
+                    pkg.checksum.checksum,
+                    digest
+                );
+            }
+            let cached = cache_path(&cache_dir, &pkg.checksum);
+            fs::create_dir_all(
+                cached
+                    .parent()
+                    .expect("cache_path always has a parent directory"),
             )?;
-            // Run the download function
-            download.getattr("download")?.call1(args)?;
-            Ok::<_, anyhow::Error>(())
-        })
-        .context("Failed to download dependencies with dnf")
+            let staged_cached = cached.with_extension("tmp");
+            fs::copy(&downloaded, &staged_cached).with_context(|| {
+                format!(
+                    "Failed to copy `{}` into the cache",
+                    downloaded.display()
+                )
+            })?;
+            fs::rename(&staged_cached, &cached)?;
+            link_or_copy(&cached, &dir.join(cache_filename(pkg)))?;
+        }
+
+        Ok(())
+    }
+
+    /// Download RPMs to a given directory, dispatching to the configured (or
+    /// autodetected) [`Backend`](super::backend::Backend)
+    pub fn download_rpms(&self, cfg: &Config, dir: &Path) -> Result<()> {
+        let packages = self.packages.iter().cloned().collect::<Vec<_>>();
+        super::backend::select(cfg.contents.backend)?.download(
+            &packages,
+            &cfg.contents.repositories,
+            &cfg.contents.gpgkeys,
+            dir,
+        )
+    }
+
+    /// Download the corresponding source RPM for every package in the lock file into `dir`,
+    /// recording each one's sha256 checksum. Used to satisfy source-redistribution obligations
+    /// (e.g. for GPL binaries) alongside the vendored binary RPMs.
+    pub fn download_source_rpms(&self, dir: &Path) -> Result<BTreeSet<SourcePackage>> {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create sources directory `{}`", dir.display()))?;
+
+        for pkg in &self.packages {
+            write::ok("Downloading", format!("source RPM for {}", pkg.name))?;
+            let status = Command::new("dnf")
+                .arg("download")
+                .arg("--source")
+                .arg("--destdir")
+                .arg(dir)
+                .arg(format!("{}-{}", pkg.name, pkg.evr))
+                .status()
+                .context("Failed to run `dnf download --source`")?;
+            if !status.success() {
+                bail!(
+                    "Failed to download source RPM for `{}-{}`",
+                    pkg.name,
+                    pkg.evr
+                );
+            }
+        }
+
+        let mut source_packages = BTreeSet::new();
+        for file in fs::read_dir(dir)? {
+            let path = file?.path();
+            if path.extension() == Some(OsStr::new("rpm")) {
+                let bytes = fs::read(&path)
+                    .with_context(|| format!("Failed to read `{}`", path.display()))?;
+                let mut sha = Sha256::new();
+                sha.update(&bytes);
+                source_packages.insert(SourcePackage {
+                    name: path
+                        .file_name()
+                        .expect("read_dir entries have a file name")
+                        .to_string_lossy()
+                        .to_string(),
+                    sha256: hex::encode(sha.finish()),
+                });
+            }
+        }
+        Ok(source_packages)
     }
 
-    /// Check GPG keys of downloaded packages against the GPG keys stored in the lockfile
+    /// Verify that `dir` contains every package recorded in this lock file (RPMs in
+    /// `dir` that the lock file doesn't record are not rejected):
+    /// each RPM's digest must match the [`Checksum`] this lock file recorded for it, and
+    /// (for repositories with `gpgcheck` enabled) its signature must verify against the
+    /// GPG keys recorded in `repo_gpg_config`, rather than trusting the host's keyring
+    /// or assuming the directory's contents are what was actually resolved.
+    ///
+    /// This is what gates package installation on a successful verification, and can
+    /// also be used standalone to validate an already-populated local mirror/cache (e.g.
+    /// one produced by `rpmoci vendor` or `rpmoci snapshot`) against a committed lock
+    /// file without rebuilding the image.
+    pub fn verify(&self, dir: &Path) -> Result<()> {
+        self.verify_checksums(dir)?;
+        self.check_gpg_keys(dir)
+    }
+
+    /// Recompute each package's digest in `dir` with the algorithm recorded in its
+    /// `Checksum`, bailing with the package name and both digests on a mismatch rather
+    /// than silently installing a corrupt or tampered-with RPM.
+    fn verify_checksums(&self, dir: &Path) -> Result<()> {
+        write::ok("Verifying", "RPM checksums")?;
+        for pkg in &self.packages {
+            let path = find_downloaded_rpm(dir, pkg)?;
+            let digest = hash_file(&path, &pkg.checksum.algorithm)?;
+            if digest != pkg.checksum.checksum {
+                bail!(
+                    "digest mismatch verifying `{}-{}`: lock file has `{}`, `{}` has `{}`",
+                    pkg.name,
+                    pkg.evr,
+                    pkg.checksum.checksum,
+                    path.display(),
+                    digest
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Check GPG keys of downloaded packages against the GPG keys stored in the lockfile.
+    ///
+    /// By default this verifies each package's header+payload signature in-process using
+    /// the `rpm` crate, so it works on minimal images that don't have `rpm`/`rpmkeys` on
+    /// PATH. Build with the `legacy-rpmkeys` feature to fall back to shelling out to those
+    /// binaries instead.
     pub fn check_gpg_keys(&self, dir: &Path) -> Result<()> {
+        #[cfg(feature = "legacy-rpmkeys")]
+        return self.check_gpg_keys_external(dir);
+        #[cfg(not(feature = "legacy-rpmkeys"))]
+        return self.check_gpg_keys_native(dir);
+    }
+
+    /// Verify each downloaded package's signature against a keyring built from the armored
+    /// GPG keys recorded in `repo_gpg_config`, entirely in-process.
+    fn check_gpg_keys_native(&self, dir: &Path) -> Result<()> {
+        write::ok("Verifying", "RPM signatures")?;
+
+        // Parse each repo's armored GPG keys into verifiers up front, tagging each with an
+        // id (repoid + position) that can be surfaced in a verification failure.
+        let mut keyrings: HashMap<&str, Vec<(String, pgp::Verifier)>> = HashMap::new();
+        for (repoid, repo_key_info) in &self.repo_gpg_config {
+            if !repo_key_info.gpgcheck {
+                continue;
+            }
+            let keys = repo_key_info
+                .keys
+                .iter()
+                .enumerate()
+                .map(|(i, key)| {
+                    let verifier = pgp::Verifier::load_from_asc_bytes(key.as_bytes())
+                        .with_context(|| format!("Failed to parse GPG key `{}-{}`", repoid, i))?;
+                    Ok((format!("{}-{}", repoid, i), verifier))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            keyrings.insert(repoid.as_str(), keys);
+        }
+
+        let gpgcheck_pkg_names = self
+            .packages
+            .iter()
+            .filter_map(|p| {
+                if keyrings.contains_key(p.repoid.as_str()) {
+                    Some(p.name.as_str())
+                } else {
+                    None
+                }
+            })
+            .collect::<HashSet<_>>();
+
+        for file in fs::read_dir(dir)? {
+            let path = file?.path();
+            if path.extension() != Some(OsStr::new("rpm")) {
+                continue;
+            }
+            let pkg = rpm::Package::open(&path)
+                .with_context(|| format!("Failed to open RPM package `{}`", path.display()))?;
+            let name = pkg
+                .metadata
+                .get_name()
+                .with_context(|| format!("Failed to get RPM name for `{}`", path.display()))?;
+            if gpgcheck_pkg_names.contains(name) {
+                verify_package_signature(&pkg, name, &keyrings)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Verify each downloaded package's signature by shelling out to `rpm --import` and
+    /// `rpmkeys --checksig`, as rpmoci did before in-process verification was added.
+    #[cfg(feature = "legacy-rpmkeys")]
+    fn check_gpg_keys_external(&self, dir: &Path) -> Result<()> {
         // Overview:
         // 1. create temporary directory
         // 2. use rpm to import all keys from the lockfile into that directory
@@ -133,6 +392,201 @@ impl Lockfile {
     }
 }
 
+/// Verify `pkg`'s signature against the keys of every gpgcheck-enabled repository in
+/// `keyrings`, not only the repository `name` was resolved from, succeeding as soon as
+/// one key verifies. Bails with the
+/// package name, the id of the last key tried and the verification failure reason if
+/// none of them do.
+fn verify_package_signature(
+    pkg: &rpm::Package,
+    name: &str,
+    keyrings: &HashMap<&str, Vec<(String, pgp::Verifier)>>,
+) -> Result<()> {
+    let mut last_failure: Option<(&str, String)> = None;
+    for keyring in keyrings.values() {
+        for (key_id, verifier) in keyring {
+            match pkg.verify_signature(verifier.clone()) {
+                Ok(()) => return Ok(()),
+                Err(e) => last_failure = Some((key_id, e.to_string())),
+            }
+        }
+    }
+    match last_failure {
+        Some((key_id, reason)) => bail!(
+            "signature verification failed for package `{}` against key `{}`: {}",
+            name,
+            key_id,
+            reason
+        ),
+        None => bail!(
+            "signature verification failed for package `{}`: no GPG key configured",
+            name
+        ),
+    }
+}
+
+/// Download `packages` into `dir` using dnf's Python API. This is the
+/// [`DnfBackend`](super::backend::DnfBackend)'s implementation of
+/// [`Backend::download`](super::backend::Backend::download).
+pub(crate) fn dnf_download(
+    packages: &[Package],
+    repositories: &[Repository],
+    gpgkeys: &[Url],
+    dir: &Path,
+) -> Result<()> {
+    Python::with_gil(|py| {
+        let base = setup_base(py, repositories, gpgkeys)?;
+        let download = PyModule::from_code(
+            py,
+            c_str!(include_str!("download.py")),
+            c_str!("resolve"),
+            c_str!("resolve"),
+        )?;
+
+        let packages = packages
+            .iter()
+            .map(|p| (p.name.clone(), p.evr.clone(), p.checksum.checksum.clone()))
+            .collect::<Vec<_>>();
+
+        let args = PyTuple::new(
+            py,
+            [
+                base.as_any(),
+                packages.into_pyobject(py)?.as_any(),
+                dir.into_pyobject(py)?.as_any(),
+            ],
+        )?;
+        // Run the download function
+        download.getattr("download")?.call1(args)?;
+        Ok::<_, anyhow::Error>(())
+    })
+    .context("Failed to download dependencies with dnf")
+}
+
+/// The default content-addressable cache directory: `$XDG_CACHE_HOME/rpmoci`, falling
+/// back to `~/.cache/rpmoci`.
+fn default_cache_dir() -> Result<PathBuf> {
+    let base = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .context("Could not determine a cache directory: set $XDG_CACHE_HOME or $HOME")?;
+    Ok(base.join(crate::NAME))
+}
+
+/// The path an RPM with the given checksum is stored at within the cache
+fn cache_path(cache_dir: &Path, checksum: &Checksum) -> PathBuf {
+    cache_dir
+        .join(checksum.algorithm.spdx_name().to_ascii_lowercase())
+        .join(&checksum.checksum)
+}
+
+/// The filename to give a package when linking it into a download directory. Since
+/// `rpmoci` only ever globs for `*.rpm` files by directory, not by name, any unique name
+/// suffices.
+fn cache_filename(pkg: &Package) -> String {
+    format!(
+        "{}-{}.rpm",
+        pkg.name,
+        pkg.evr
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                c
+            } else {
+                '_'
+            })
+            .collect::<String>()
+    )
+}
+
+/// If a verified cache entry already exists for `pkg`, link it into `dir` and return
+/// `true`. Returns `false` (without error) if there's no cache entry, or if the cached
+/// file is stale/corrupt and should be re-downloaded.
+fn try_link_from_cache(pkg: &Package, cache_dir: &Path, dir: &Path) -> Result<bool> {
+    let cached = cache_path(cache_dir, &pkg.checksum);
+    if !cached.is_file() {
+        return Ok(false);
+    }
+    if hash_file(&cached, &pkg.checksum.algorithm)? != pkg.checksum.checksum {
+        return Ok(false);
+    }
+    link_or_copy(&cached, &dir.join(cache_filename(pkg)))?;
+    Ok(true)
+}
+
+fn link_or_copy(src: &Path, dst: &Path) -> Result<()> {
+    if dst.exists() {
+        return Ok(());
+    }
+    if fs::hard_link(src, dst).is_err() {
+        fs::copy(src, dst).with_context(|| {
+            format!("Failed to link or copy `{}` to `{}`", src.display(), dst.display())
+        })?;
+    }
+    Ok(())
+}
+
+/// Find the RPM that dnf downloaded for `pkg` within `staging`, identifying it by
+/// querying each candidate file's own package name (rather than trying to parse dnf's
+/// NEVRA filename convention, which varies in how it encodes epoch).
+fn find_downloaded_rpm(staging: &Path, pkg: &Package) -> Result<PathBuf> {
+    for entry in fs::read_dir(staging)? {
+        let path = entry?.path();
+        if path.extension() == Some(OsStr::new("rpm")) && rpm_name(&path)? == pkg.name {
+            return Ok(path);
+        }
+    }
+    bail!(
+        "dnf did not download a package named `{}` into `{}`",
+        pkg.name,
+        staging.display()
+    );
+}
+
+fn rpm_name(rpm_path: &Path) -> Result<String> {
+    let output = Command::new("rpm")
+        .arg("-qp")
+        .arg("--qf")
+        .arg("%{NAME}")
+        .arg(rpm_path)
+        .output()
+        .context("Failed to run `rpm -qp`")?;
+    if !output.status.success() {
+        bail!(
+            "rpm -qp failed for `{}`: {}",
+            rpm_path.display(),
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn message_digest(algorithm: &Algorithm) -> MessageDigest {
+    match algorithm {
+        Algorithm::MD5 => MessageDigest::md5(), //Devskim: ignore DS126858
+        Algorithm::SHA1 => MessageDigest::sha1(), //Devskim: ignore DS126858
+        Algorithm::SHA256 => MessageDigest::sha256(),
+        Algorithm::SHA384 => MessageDigest::sha384(),
+        Algorithm::SHA512 => MessageDigest::sha512(),
+    }
+}
+
+/// Hash a file on disk with the given algorithm, returning the hex-encoded digest
+pub(crate) fn hash_file(path: &Path, algorithm: &Algorithm) -> Result<String> {
+    let mut file =
+        File::open(path).with_context(|| format!("Failed to open `{}`", path.display()))?;
+    let mut hasher = Hasher::new(message_digest(algorithm))?;
+    let mut buf = [0u8; 65536];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n])?;
+    }
+    Ok(hex::encode(hasher.finish()?))
+}
+
+#[cfg(feature = "legacy-rpmkeys")]
 fn load_key(tmp_dir: &TempDir, name: &str, key: &str) -> Result<(), anyhow::Error> {
     let gpg_path = tmp_dir.path().join(name);
     let mut gpg_key =
@@ -152,6 +606,7 @@ fn load_key(tmp_dir: &TempDir, name: &str, key: &str) -> Result<(), anyhow::Erro
 }
 
 /// Verify a package signature using rpmkeys
+#[cfg(feature = "legacy-rpmkeys")]
 fn check_pkg_signature(rpm_path: &Path, root: &Path) -> Result<()> {
     let output = Command::new("rpmkeys")
         .arg("--root")
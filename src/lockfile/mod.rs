@@ -24,9 +24,11 @@ use serde::{Deserialize, Serialize};
 use crate::write;
 use crate::{config::Config, NAME};
 
+mod backend;
 mod build;
 mod download;
 mod resolve;
+mod snapshot;
 
 /// Represents an rpmoci lockfile
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -39,6 +41,19 @@ pub struct Lockfile {
     repo_gpg_config: HashMap<String, RepoKeyInfo>,
     #[serde(default)]
     global_key_specs: Vec<url::Url>,
+    /// Checksums of the source RPMs corresponding to `packages`, recorded
+    /// when vendoring/updating with source inclusion enabled
+    #[serde(default)]
+    source_packages: BTreeSet<SourcePackage>,
+}
+
+/// The sha256 checksum of a source RPM corresponding to one of `Lockfile::packages`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, PartialOrd, Eq, Ord)]
+pub struct SourcePackage {
+    /// The source RPM file name, e.g. `foo-1.0-1.src.rpm`
+    name: String,
+    /// The sha256 checksum of the source RPM
+    sha256: String,
 }
 
 /// A package that the user has specified locally
@@ -68,40 +83,47 @@ struct DnfOutput {
 }
 
 /// GPG key configuration for a specified repository
-#[derive(Debug, Serialize, Deserialize, Clone)]
-struct RepoKeyInfo {
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub(crate) struct RepoKeyInfo {
     /// Is GPG checking enabled for this repository
-    gpgcheck: bool,
+    pub(crate) gpgcheck: bool,
     /// contents of any keys specified via repository configuration
-    keys: Vec<String>,
+    pub(crate) keys: Vec<String>,
 }
 
 /// A resolved package
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, PartialOrd, Eq, Ord)]
-struct Package {
+pub struct Package {
     /// The package name
-    name: String,
+    pub name: String,
     /// The package epoch-version-release
-    evr: String,
+    pub evr: String,
     /// The package checksum
-    checksum: Checksum,
+    pub(crate) checksum: Checksum,
     /// The id of the package's repository
-    repoid: String,
+    pub(crate) repoid: String,
+}
+
+impl Package {
+    /// The id of the repository this package was resolved from
+    pub(crate) fn repoid(&self) -> &str {
+        &self.repoid
+    }
 }
 
 /// Checksum of RPM package
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, PartialOrd, Eq, Ord)]
-struct Checksum {
+pub(crate) struct Checksum {
     /// The algorithm of the checksum
-    algorithm: Algorithm,
+    pub(crate) algorithm: Algorithm,
     /// The checksum value
-    checksum: String,
+    pub(crate) checksum: String,
 }
 
 /// Algorithms supported by RPM for checksums
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, PartialOrd, Eq, Ord)]
 #[serde(rename_all = "lowercase")]
-enum Algorithm {
+pub(crate) enum Algorithm {
     MD5,  //Devskim: ignore DS126858
     SHA1, //Devskim: ignore DS126858
     SHA256,
@@ -109,7 +131,35 @@ enum Algorithm {
     SHA512,
 }
 
+impl Algorithm {
+    /// The SPDX checksum algorithm name for this algorithm, e.g. `SHA256`
+    pub(crate) fn spdx_name(&self) -> &'static str {
+        match self {
+            Algorithm::MD5 => "MD5",
+            Algorithm::SHA1 => "SHA1",
+            Algorithm::SHA256 => "SHA256",
+            Algorithm::SHA384 => "SHA384",
+            Algorithm::SHA512 => "SHA512",
+        }
+    }
+}
+
 impl Lockfile {
+    /// Iterate over the packages resolved in this lockfile
+    pub fn iter_packages(&self) -> impl Iterator<Item = &Package> {
+        self.packages.iter()
+    }
+
+    /// Record the source RPM checksums resolved for this lockfile's packages
+    pub fn set_source_packages(&mut self, source_packages: BTreeSet<SourcePackage>) {
+        self.source_packages = source_packages;
+    }
+
+    /// The configured GPG verification info for each repository, keyed by repo id
+    pub(crate) fn repo_gpg_config(&self) -> &HashMap<String, RepoKeyInfo> {
+        &self.repo_gpg_config
+    }
+
     /// Returns true if the lockfile is compatible with the
     /// given configuration, false otherwise
     #[must_use]
@@ -196,4 +246,239 @@ impl Lockfile {
 
         Ok(())
     }
+
+    /// Print a columnar upgrade report for `rpmoci update --dry-run`: for every package
+    /// that would change, or whose version is pinned below the latest available, show its
+    /// current, latest-available and selected version, and a note classifying the package
+    /// as `compatible` (selected matches latest), `pinned` (a version requirement in
+    /// `cfg` is holding it below latest) or `incompatible` (selected is neither).
+    ///
+    /// `self` is the candidate lockfile that would be written (pins respected unless
+    /// `--breaking` was passed), `latest` is the same resolution with all pins ignored.
+    pub fn print_dry_run_report(
+        &self,
+        previous: Option<&Lockfile>,
+        latest: &Lockfile,
+        cfg: &Config,
+    ) -> Result<()> {
+        let pinned_names: BTreeSet<&str> = cfg
+            .contents
+            .packages
+            .iter()
+            .filter(|spec| crate::config::is_pinned_spec(spec))
+            .map(|spec| crate::config::pinned_spec_name(spec))
+            .collect();
+
+        let current = previous
+            .map(|previous| {
+                previous
+                    .packages
+                    .iter()
+                    .map(|pkg| (pkg.name.as_str(), pkg.evr.as_str()))
+                    .collect::<BTreeMap<_, _>>()
+            })
+            .unwrap_or_default();
+        let latest = latest
+            .packages
+            .iter()
+            .map(|pkg| (pkg.name.as_str(), pkg.evr.as_str()))
+            .collect::<BTreeMap<_, _>>();
+        let selected = self
+            .packages
+            .iter()
+            .map(|pkg| (pkg.name.as_str(), pkg.evr.as_str()))
+            .collect::<BTreeMap<_, _>>();
+
+        let mut names: BTreeSet<&str> = BTreeSet::new();
+        names.extend(current.keys());
+        names.extend(latest.keys());
+        names.extend(selected.keys());
+
+        let mut rows = Vec::new();
+        for name in names {
+            let current_evr = current.get(name).copied().unwrap_or("-");
+            let latest_evr = latest.get(name).copied().unwrap_or("-");
+            let selected_evr = selected.get(name).copied().unwrap_or("-");
+            if current_evr == selected_evr && selected_evr == latest_evr {
+                // Nothing would change and nothing is being held back: not worth reporting.
+                continue;
+            }
+            let note = if selected_evr == latest_evr {
+                "compatible"
+            } else if pinned_names.contains(name) {
+                "pinned"
+            } else {
+                "incompatible"
+            };
+            rows.push([
+                name.to_string(),
+                current_evr.to_string(),
+                latest_evr.to_string(),
+                selected_evr.to_string(),
+                note.to_string(),
+            ]);
+        }
+
+        if rows.is_empty() {
+            write::ok("Up-to-date", "no package updates available")?;
+            return Ok(());
+        }
+
+        let headers = ["PACKAGE", "CURRENT", "LATEST", "SELECTED", "NOTE"];
+        let mut widths = headers.map(str::len);
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row.iter()) {
+                *width = (*width).max(cell.len());
+            }
+        }
+        println!(
+            "{:w0$}  {:w1$}  {:w2$}  {:w3$}  {:w4$}",
+            headers[0],
+            headers[1],
+            headers[2],
+            headers[3],
+            headers[4],
+            w0 = widths[0],
+            w1 = widths[1],
+            w2 = widths[2],
+            w3 = widths[3],
+            w4 = widths[4],
+        );
+        for row in &rows {
+            println!(
+                "{:w0$}  {:w1$}  {:w2$}  {:w3$}  {:w4$}",
+                row[0],
+                row[1],
+                row[2],
+                row[3],
+                row[4],
+                w0 = widths[0],
+                w1 = widths[1],
+                w2 = widths[2],
+                w3 = widths[3],
+                w4 = widths[4],
+            );
+        }
+        Ok(())
+    }
+
+    /// Compute a structured diff against a previous lock file, for CI pipelines that want
+    /// to gate on exactly which packages moved instead of scraping [`Lockfile::print_updates`]'s
+    /// formatted stderr text.
+    pub fn diff(&self, previous: Option<&Lockfile>) -> LockfileDiff {
+        let mut new = self
+            .packages
+            .iter()
+            .map(|pkg| (pkg.name.as_str(), pkg))
+            .collect::<BTreeMap<_, _>>();
+        let old = previous
+            .map(|previous| {
+                previous
+                    .packages
+                    .iter()
+                    .map(|pkg| (pkg.name.as_str(), pkg))
+                    .collect::<BTreeMap<_, _>>()
+            })
+            .unwrap_or_default();
+
+        let mut removed = Vec::new();
+        let mut changed = Vec::new();
+        for (name, old_pkg) in &old {
+            match new.remove(name) {
+                Some(new_pkg) if new_pkg.evr != old_pkg.evr || new_pkg.checksum != old_pkg.checksum => {
+                    changed.push(PackageChange {
+                        name: name.to_string(),
+                        old_evr: old_pkg.evr.clone(),
+                        new_evr: new_pkg.evr.clone(),
+                        old_checksum: old_pkg.checksum.checksum.clone(),
+                        new_checksum: new_pkg.checksum.checksum.clone(),
+                    });
+                }
+                Some(_) => {}
+                None => removed.push(PackageSummary::from(*old_pkg)),
+            }
+        }
+        let added = new.into_values().map(PackageSummary::from).collect();
+
+        let old_local_packages = previous
+            .map(|previous| previous.local_packages.clone())
+            .unwrap_or_default();
+        let local_packages_changed = old_local_packages != self.local_packages;
+
+        let old_repo_gpg_config = previous
+            .map(|previous| previous.repo_gpg_config.clone())
+            .unwrap_or_default();
+        let all_repoids: BTreeSet<&String> = old_repo_gpg_config
+            .keys()
+            .chain(self.repo_gpg_config.keys())
+            .collect();
+        let repo_gpg_config_changed = all_repoids
+            .into_iter()
+            .filter(|repoid| old_repo_gpg_config.get(**repoid) != self.repo_gpg_config.get(**repoid))
+            .cloned()
+            .collect();
+
+        LockfileDiff {
+            added,
+            removed,
+            changed,
+            local_packages_changed,
+            repo_gpg_config_changed,
+        }
+    }
+}
+
+/// A structured diff between two lock files, serializable to JSON for CI consumption.
+#[derive(Debug, Serialize)]
+pub struct LockfileDiff {
+    /// Packages present in the new lock file but not the previous one
+    pub added: Vec<PackageSummary>,
+    /// Packages present in the previous lock file but not the new one
+    pub removed: Vec<PackageSummary>,
+    /// Packages present in both lock files whose version or checksum changed
+    pub changed: Vec<PackageChange>,
+    /// Whether the set of local packages, or any of their recorded dependencies, changed
+    pub local_packages_changed: bool,
+    /// Ids of repositories whose GPG verification configuration (key material or
+    /// whether `gpgcheck` is enabled) changed
+    pub repo_gpg_config_changed: Vec<String>,
+}
+
+/// A package recorded in a [`LockfileDiff`]'s `added` or `removed` list
+#[derive(Debug, Serialize)]
+pub struct PackageSummary {
+    /// The package name
+    pub name: String,
+    /// The package epoch-version-release
+    pub evr: String,
+    /// The algorithm used for `checksum`, e.g. `SHA256`
+    pub checksum_algorithm: String,
+    /// The package checksum
+    pub checksum: String,
+}
+
+impl From<&Package> for PackageSummary {
+    fn from(pkg: &Package) -> Self {
+        PackageSummary {
+            name: pkg.name.clone(),
+            evr: pkg.evr.clone(),
+            checksum_algorithm: pkg.checksum.algorithm.spdx_name().to_string(),
+            checksum: pkg.checksum.checksum.clone(),
+        }
+    }
+}
+
+/// A package present in both lock files whose version and/or checksum changed
+#[derive(Debug, Serialize)]
+pub struct PackageChange {
+    /// The package name
+    pub name: String,
+    /// The epoch-version-release in the previous lock file
+    pub old_evr: String,
+    /// The epoch-version-release in the new lock file
+    pub new_evr: String,
+    /// The checksum in the previous lock file
+    pub old_checksum: String,
+    /// The checksum in the new lock file
+    pub new_checksum: String,
 }
@@ -12,7 +12,7 @@
 //!
 //! You should have received a copy of the GNU General Public License
 //! along with this program.  If not, see <https://www.gnu.org/licenses/>.
-use std::collections::{BTreeSet, HashMap};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::env;
 use std::ops::Deref;
 
@@ -23,30 +23,21 @@ use pyo3::types::{IntoPyDict, PyDict, PyString, PyTuple};
 use url::Url;
 
 use super::{DnfOutput, LocalPackage, Lockfile};
+use crate::config::BackendKind;
 use crate::config::Config;
 use crate::config::Repository;
+use crate::write;
 
 impl Lockfile {
-    /// Perform dependency resolution on the given package specs
+    /// Perform dependency resolution on the given package specs, dispatching to the
+    /// configured (or autodetected) [`Backend`](super::backend::Backend)
     pub(crate) fn resolve(
         pkg_specs: Vec<String>,
         repositories: &[Repository],
         gpgkeys: Vec<Url>,
+        backend: Option<BackendKind>,
     ) -> Result<Self> {
-        let output = Python::with_gil(|py| {
-            // Resolve is a compiled in python module for resolving dependencies
-            let resolve =
-                PyModule::from_code(py, include_str!("resolve.py"), "resolve", "resolve")?;
-            let base = setup_base(py, repositories, &gpgkeys)?;
-
-            let args = PyTuple::new(py, &[base.to_object(py), pkg_specs.to_object(py)]);
-            // Run the resolve function, returning a json string, which we shall deserialize.
-            let val: String = resolve.getattr("resolve")?.call1(args)?.extract()?;
-            Ok::<_, anyhow::Error>(val)
-        })
-        .context("Failed to resolve dependencies with dnf")?;
-
-        let results: DnfOutput = serde_json::from_str(&output)?;
+        let results = super::backend::select(backend)?.resolve(&pkg_specs, repositories, &gpgkeys)?;
         Ok(Lockfile {
             pkg_specs,
             packages: results.packages.into_iter().collect(),
@@ -62,6 +53,7 @@ impl Lockfile {
             cfg.contents.packages.clone(),
             &cfg.contents.repositories,
             cfg.contents.gpgkeys.clone(),
+            cfg.contents.backend,
         )
     }
 
@@ -79,6 +71,7 @@ impl Lockfile {
             local,
             &cfg.contents.repositories,
             cfg.contents.gpgkeys.clone(),
+            cfg.contents.backend,
         )?;
         Ok(lockfile.local_packages)
     }
@@ -102,6 +95,45 @@ impl Lockfile {
             requires,
             &cfg.contents.repositories,
             cfg.contents.gpgkeys.clone(),
+            cfg.contents.backend,
+        )?;
+        lockfile.local_packages = self.local_packages.clone();
+        lockfile.pkg_specs = cfg.contents.packages.clone();
+        Ok(lockfile)
+    }
+
+    /// Re-resolve only `targets` (package names), holding every other package already
+    /// in this lock file pinned to its exact existing `evr` by feeding the solver a
+    /// `name-evr` constraint for it, so the packages the caller didn't ask about don't
+    /// silently move the way a full re-resolve would. Mirrors Cargo's
+    /// `cargo update -p <pkg>`.
+    pub fn resolve_update(&self, cfg: &Config, targets: &[String]) -> Result<Self> {
+        let targets: HashSet<&str> = targets.iter().map(String::as_str).collect();
+
+        let requires = cfg
+            .contents
+            .packages
+            .clone()
+            .into_iter()
+            .filter(|spec| !spec.ends_with(".rpm"))
+            .chain(
+                self.packages
+                    .iter()
+                    .filter(|pkg| !targets.contains(pkg.name.as_str()))
+                    .map(|pkg| format!("{}-{}", pkg.name, pkg.evr)),
+            )
+            .chain(
+                self.local_packages
+                    .iter()
+                    .flat_map(|pkg| pkg.requires.clone()),
+            )
+            .collect::<Vec<_>>();
+
+        let mut lockfile = Self::resolve(
+            requires,
+            &cfg.contents.repositories,
+            cfg.contents.gpgkeys.clone(),
+            cfg.contents.backend,
         )?;
         lockfile.local_packages = self.local_packages.clone();
         lockfile.pkg_specs = cfg.contents.packages.clone();
@@ -109,6 +141,30 @@ impl Lockfile {
     }
 }
 
+/// Resolve `pkg_specs` against `repositories` using dnf's Python API, returning the raw
+/// dnf/yum-format output. This is the [`DnfBackend`](super::backend::DnfBackend)'s
+/// implementation of [`Backend::resolve`](super::backend::Backend::resolve).
+pub(crate) fn dnf_resolve(
+    pkg_specs: &[String],
+    repositories: &[Repository],
+    gpgkeys: &[Url],
+) -> Result<DnfOutput> {
+    let pkg_specs = pkg_specs.to_vec();
+    let output = Python::with_gil(|py| {
+        // Resolve is a compiled in python module for resolving dependencies
+        let resolve = PyModule::from_code(py, include_str!("resolve.py"), "resolve", "resolve")?;
+        let base = setup_base(py, repositories, gpgkeys)?;
+
+        let args = PyTuple::new(py, &[base.to_object(py), pkg_specs.to_object(py)]);
+        // Run the resolve function, returning a json string, which we shall deserialize.
+        let val: String = resolve.getattr("resolve")?.call1(args)?.extract()?;
+        Ok::<_, anyhow::Error>(val)
+    })
+    .context("Failed to resolve dependencies with dnf")?;
+
+    Ok(serde_json::from_str(&output)?)
+}
+
 /// A wrapper around the dnf.Base object which ensures that plugins are unloaded
 pub(crate) struct Base<'a> {
     value: &'a PyAny,
@@ -199,12 +255,13 @@ pub(crate) fn setup_base<'a>(
                     args,
                     Some(repo_kwargs(
                         url,
+                        &[],
                         &HashMap::new(),
                         gpgkeys,
                         repo_username(&repo.repo_id()),
                         repo_password(&repo.repo_id()),
                         py,
-                    )),
+                    )?),
                 )?;
             }
             Repository::Id(_) => {}
@@ -214,12 +271,13 @@ pub(crate) fn setup_base<'a>(
                     args,
                     Some(repo_kwargs(
                         &definition.url,
+                        &definition.mirrors,
                         &definition.options,
                         gpgkeys,
                         repo_username(&repo.repo_id()),
                         repo_password(&repo.repo_id()),
                         py,
-                    )),
+                    )?),
                 )?;
             }
         }
@@ -244,12 +302,13 @@ fn default_repo_options() -> HashMap<String, String> {
 
 pub(crate) fn repo_kwargs<'p>(
     repo_url: &Url,
+    mirrors: &[Url],
     repo_options: &HashMap<String, String>,
     gpgkeys: &[Url],
     username: Option<String>,
     password: Option<String>,
     py: Python<'p>,
-) -> &'p PyDict {
+) -> Result<&'p PyDict> {
     let mut kwargs = Vec::new();
     let mut default_repo_options = default_repo_options();
 
@@ -263,9 +322,35 @@ pub(crate) fn repo_kwargs<'p>(
     // If the repo definition specified gpgkey, this option won't be used
     default_repo_options.insert("gpgkey".to_string(), global_gpgkeys.clone());
 
+    // dnf tries each baseurl in order, falling back to the next on failure, so
+    // mirrors are simply appended after the primary url.
+    let baseurls = std::iter::once(repo_url)
+        .chain(mirrors.iter())
+        .collect::<Vec<_>>();
+    if mirrors.is_empty() {
+        write::ok("Configuring", format!("repository `{}`", repo_url))?;
+    } else {
+        write::ok(
+            "Configuring",
+            format!(
+                "repository `{}` with {} mirror(s): {}",
+                repo_url,
+                mirrors.len(),
+                mirrors
+                    .iter()
+                    .map(Url::as_str)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        )?;
+    }
     kwargs.push((
         "baseurl".to_string(),
-        [PyString::new(py, repo_url.as_ref())].to_object(py),
+        baseurls
+            .into_iter()
+            .map(|url| PyString::new(py, url.as_ref()))
+            .collect::<Vec<_>>()
+            .to_object(py),
     ));
 
     for (key, val) in repo_options {
@@ -293,7 +378,7 @@ pub(crate) fn repo_kwargs<'p>(
         kwargs.push(("password".to_string(), password.to_object(py)));
     }
 
-    kwargs.into_py_dict(py)
+    Ok(kwargs.into_py_dict(py))
 }
 
 fn repo_username(repo_id: &str) -> Option<String> {
@@ -0,0 +1,240 @@
+//! Materialize a self-contained local mirror of a lock file's packages, for air-gapped
+//! and reproducible builds: every locked RPM is downloaded (with checksum verification)
+//! into a `Packages/` directory laid out as a yum/dnf repository, and minimal
+//! `repodata/repomd.xml` + `repodata/primary.xml.gz` are synthesized directly from the
+//! lock file's own package records (name/evr/checksum/location are already known),
+//! rather than shelling out to `createrepo_c`. The configured GPG keys are written
+//! alongside so the snapshot is fully self-describing.
+//!
+//! The resulting directory can be pointed at directly as a `file://` [`Repository::Url`]
+//! in `rpmoci.toml`: dnf's Python API already resolves `file://` baseurls natively, so
+//! [`setup_base`](super::resolve::setup_base) needs no changes to build against one.
+//!
+//! This snapshot omits `filelists.xml`/`other.xml`: they're only consulted by dnf when
+//! resolving a spec against a file `Provides` (e.g. `/usr/bin/foo`) rather than a package
+//! name, which `rpmoci.toml` specs don't do.
+//!
+//! Copyright (C) Microsoft Corporation.
+//!
+//! This program is free software: you can redistribute it and/or modify
+//! it under the terms of the GNU General Public License as published by
+//! the Free Software Foundation, either version 3 of the License, or
+//! (at your option) any later version.
+//!
+//! This program is distributed in the hope that it will be useful,
+//! but WITHOUT ANY WARRANTY; without even the implied warranty of
+//! MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//! GNU General Public License for more details.
+//!
+//! You should have received a copy of the GNU General Public License
+//! along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use flate2::{write::GzEncoder, Compression};
+use openssl::sha::Sha256;
+
+use super::download::hash_file;
+use super::{Lockfile, Package, RepoKeyInfo};
+use crate::config::Config;
+use crate::write;
+
+impl Lockfile {
+    /// Download every package in this lock file into `dir/Packages`, write the GPG keys
+    /// recorded in `repo_gpg_config` alongside, and synthesize `dir/repodata/repomd.xml`
+    /// + `dir/repodata/primary.xml.gz`, so that `dir` is a complete, self-contained
+    /// yum/dnf repository a later build can run against entirely offline by pointing a
+    /// `rpmoci.toml` repository at `file://<dir>`.
+    pub fn snapshot_repo(&self, cfg: &Config, dir: &Path) -> Result<()> {
+        let packages_dir = dir.join("Packages");
+        self.download_rpms(cfg, &packages_dir)
+            .context("Failed to download packages for snapshot")?;
+
+        let packages = self.packages.iter().collect::<Vec<_>>();
+        let located = locate_downloaded_packages(&packages_dir, &packages)?;
+
+        write_gpg_keys(dir, &self.repo_gpg_config)?;
+        write_repodata(dir, &packages, &located)?;
+
+        write::ok(
+            "Snapshotted",
+            format!("{} package(s) to `{}`", packages.len(), dir.display()),
+        )?;
+        Ok(())
+    }
+}
+
+/// Match every downloaded RPM in `packages_dir` back to its lock file record by the
+/// package name recorded in its own header (rather than trying to parse a backend's
+/// download-filename convention), verifying each one's checksum against the lock file
+/// along the way.
+fn locate_downloaded_packages(
+    packages_dir: &Path,
+    packages: &[&Package],
+) -> Result<HashMap<String, PathBuf>> {
+    let mut by_name = HashMap::new();
+    for entry in fs::read_dir(packages_dir)
+        .with_context(|| format!("Failed to read `{}`", packages_dir.display()))?
+    {
+        let path = entry?.path();
+        if path.extension() != Some(std::ffi::OsStr::new("rpm")) {
+            continue;
+        }
+        let pkg = rpm::Package::open(&path)
+            .with_context(|| format!("Failed to open RPM package `{}`", path.display()))?;
+        let name = pkg
+            .metadata
+            .get_name()
+            .with_context(|| format!("Failed to get RPM name for `{}`", path.display()))?
+            .to_string();
+        by_name.insert(name, path);
+    }
+
+    let mut located = HashMap::new();
+    for pkg in packages {
+        let path = by_name
+            .remove(&pkg.name)
+            .with_context(|| format!("`{}` was not among the downloaded packages", pkg.name))?;
+        let digest = hash_file(&path, &pkg.checksum.algorithm)?;
+        if digest != pkg.checksum.checksum {
+            bail!(
+                "digest mismatch snapshotting `{}-{}`: lock file has `{}`, downloaded file has `{}`",
+                pkg.name,
+                pkg.evr,
+                pkg.checksum.checksum,
+                digest
+            );
+        }
+        located.insert(pkg.name.clone(), path);
+    }
+    Ok(located)
+}
+
+/// Write each repository's armored GPG keys into `dir` as `RPM-GPG-KEY-<repoid>-<i>`.
+fn write_gpg_keys(dir: &Path, repo_gpg_config: &HashMap<String, RepoKeyInfo>) -> Result<()> {
+    for (repoid, info) in repo_gpg_config {
+        for (i, key) in info.keys.iter().enumerate() {
+            let path = dir.join(format!("RPM-GPG-KEY-{}-{}", repoid, i));
+            fs::write(&path, key)
+                .with_context(|| format!("Failed to write `{}`", path.display()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Write `dir/repodata/primary.xml.gz` (built from `packages`/`located`) and the
+/// `repomd.xml` that references it.
+fn write_repodata(
+    dir: &Path,
+    packages: &[&Package],
+    located: &HashMap<String, PathBuf>,
+) -> Result<()> {
+    let repodata_dir = dir.join("repodata");
+    fs::create_dir_all(&repodata_dir)
+        .with_context(|| format!("Failed to create `{}`", repodata_dir.display()))?;
+
+    let mut primary = String::new();
+    primary.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    primary.push_str(&format!(
+        "<metadata xmlns=\"http://linux.duke.edu/metadata/common\" xmlns:rpm=\"http://linux.duke.edu/metadata/rpm\" packages=\"{}\">\n",
+        packages.len()
+    ));
+    for pkg in packages {
+        let path = located
+            .get(&pkg.name)
+            .expect("every package was located or snapshot_repo would already have failed");
+        let filename = path
+            .file_name()
+            .expect("downloaded RPMs always have a file name")
+            .to_string_lossy();
+        let arch = rpm::Package::open(path)
+            .with_context(|| format!("Failed to open RPM package `{}`", path.display()))?
+            .metadata
+            .get_arch()
+            .with_context(|| format!("Failed to get RPM arch for `{}`", path.display()))?
+            .to_string();
+        let (epoch, version, release) = split_evr(&pkg.evr);
+        primary.push_str(&format!(
+            "  <package type=\"rpm\">\n    \
+               <name>{name}</name>\n    \
+               <arch>{arch}</arch>\n    \
+               <version epoch=\"{epoch}\" ver=\"{version}\" rel=\"{release}\"/>\n    \
+               <checksum type=\"{ctype}\" pkgid=\"YES\">{csum}</checksum>\n    \
+               <location href=\"Packages/{filename}\"/>\n  \
+             </package>\n",
+            name = xml_escape(&pkg.name),
+            arch = xml_escape(&arch),
+            epoch = epoch,
+            version = xml_escape(version),
+            release = xml_escape(release),
+            ctype = pkg.checksum.algorithm.spdx_name().to_ascii_lowercase(),
+            csum = pkg.checksum.checksum,
+            filename = xml_escape(&filename),
+        ));
+    }
+    primary.push_str("</metadata>\n");
+
+    let open_checksum = sha256_hex(primary.as_bytes());
+    let open_size = primary.len();
+
+    let primary_path = repodata_dir.join("primary.xml.gz");
+    let mut encoder = GzEncoder::new(
+        File::create(&primary_path)
+            .with_context(|| format!("Failed to create `{}`", primary_path.display()))?,
+        Compression::default(),
+    );
+    encoder
+        .write_all(primary.as_bytes())
+        .with_context(|| format!("Failed to write `{}`", primary_path.display()))?;
+    encoder.finish()?;
+
+    let compressed = fs::read(&primary_path)
+        .with_context(|| format!("Failed to read `{}`", primary_path.display()))?;
+    let checksum = sha256_hex(&compressed);
+    let size = compressed.len();
+    let revision = chrono::Utc::now().timestamp();
+
+    let repomd = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <repomd xmlns=\"http://linux.duke.edu/metadata/repo\">\n  \
+           <revision>{revision}</revision>\n  \
+           <data type=\"primary\">\n    \
+             <checksum type=\"sha256\">{checksum}</checksum>\n    \
+             <open-checksum type=\"sha256\">{open_checksum}</open-checksum>\n    \
+             <location href=\"repodata/primary.xml.gz\"/>\n    \
+             <timestamp>{revision}</timestamp>\n    \
+             <size>{size}</size>\n    \
+             <open-size>{open_size}</open-size>\n  \
+           </data>\n\
+         </repomd>\n",
+    );
+    let repomd_path = repodata_dir.join("repomd.xml");
+    fs::write(&repomd_path, repomd)
+        .with_context(|| format!("Failed to write `{}`", repomd_path.display()))?;
+
+    Ok(())
+}
+
+/// Split an `epoch:version-release` string (as stored in [`Package::evr`]) into its
+/// three components, defaulting epoch to `0` if absent.
+fn split_evr(evr: &str) -> (&str, &str, &str) {
+    let (epoch, rest) = evr.split_once(':').unwrap_or(("0", evr));
+    let (version, release) = rest.rsplit_once('-').unwrap_or((rest, ""));
+    (epoch, version, release)
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    let mut sha = Sha256::new();
+    sha.update(bytes);
+    hex::encode(sha.finish())
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,79 @@
+//! Module for embedding and extracting a resolved lock file as OCI image
+//! manifest provenance, so an image carries everything needed to rebuild or
+//! audit itself
+//!
+//! Copyright (C) Microsoft Corporation.
+//!
+//! This program is free software: you can redistribute it and/or modify
+//! it under the terms of the GNU General Public License as published by
+//! the Free Software Foundation, either version 3 of the License, or
+//! (at your option) any later version.
+//!
+//! This program is distributed in the hope that it will be useful,
+//! but WITHOUT ANY WARRANTY; without even the implied warranty of
+//! MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//! GNU General Public License for more details.
+//!
+//! You should have received a copy of the GNU General Public License
+//! along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use oci_spec::image::{ImageIndex, ImageManifest};
+
+use crate::NAME;
+
+/// The well-known manifest annotation rpmoci embeds the resolved lock file under
+const LOCKFILE_ANNOTATION: &str = "dev.rpmoci.lockfile";
+
+/// Serialize `lockfile` the same way [`crate::lockfile::Lockfile::write_to_file`] does,
+/// suitable for embedding as a manifest annotation or writing back out to a `.lock` file.
+pub(crate) fn lockfile_annotation(lockfile: &crate::lockfile::Lockfile) -> Result<(String, String)> {
+    let contents = format!(
+        "# This file is @generated by {}\n# It is not intended for manual editing.\n{}",
+        NAME.to_ascii_uppercase(),
+        toml::to_string_pretty(lockfile)?
+    );
+    Ok((LOCKFILE_ANNOTATION.to_string(), contents))
+}
+
+/// Read back the lock file embedded in the manifest tagged `tag` in the OCI image
+/// layout directory at `image`.
+pub(crate) fn extract_lockfile(image: impl AsRef<Path>, tag: &str) -> Result<String> {
+    let image = image.as_ref();
+    let index_path = image.join("index.json");
+    let index = ImageIndex::from_file(&index_path)
+        .with_context(|| format!("Failed to read `{}`", index_path.display()))?;
+
+    let manifest_descriptor = index
+        .manifests()
+        .iter()
+        .find(|m| {
+            m.annotations()
+                .as_ref()
+                .and_then(|a| a.get("org.opencontainers.image.ref.name"))
+                .is_some_and(|t| t == tag)
+        })
+        .with_context(|| format!("No manifest tagged `{}` found in `{}`", tag, image.display()))?;
+
+    let digest = manifest_descriptor.digest().to_string();
+    let (algorithm, hex) = digest
+        .split_once(':')
+        .with_context(|| format!("Unexpected digest format `{}`", digest))?;
+    let manifest_path = image.join("blobs").join(algorithm).join(hex);
+    let manifest = ImageManifest::from_file(&manifest_path)
+        .with_context(|| format!("Failed to read `{}`", manifest_path.display()))?;
+
+    manifest
+        .annotations()
+        .as_ref()
+        .and_then(|a| a.get(LOCKFILE_ANNOTATION))
+        .cloned()
+        .with_context(|| {
+            format!(
+                "Image `{}:{}` does not have an embedded lock file. Was it built with `--embed-lockfile`?",
+                image.display(),
+                tag
+            )
+        })
+}
@@ -0,0 +1,60 @@
+//! Module for pushing a built OCI image layout directly to a remote registry
+//!
+//! Copyright (C) Microsoft Corporation.
+//!
+//! This program is free software: you can redistribute it and/or modify
+//! it under the terms of the GNU General Public License as published by
+//! the Free Software Foundation, either version 3 of the License, or
+//! (at your option) any later version.
+//!
+//! This program is distributed in the hope that it will be useful,
+//! but WITHOUT ANY WARRANTY; without even the implied warranty of
+//! MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//! GNU General Public License for more details.
+//!
+//! You should have received a copy of the GNU General Public License
+//! along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+
+use crate::write;
+
+/// Push the image tagged `tag` in the OCI layout at `image` to `dest` (any destination
+/// reference understood by `skopeo`, e.g. `docker://registry.example.com/repo:tag`).
+/// `digest` is the manifest digest [`crate::imager::Imager::create_image`] just produced
+/// for `tag`, recorded here purely for the push log line, so a reader can confirm exactly
+/// which build is being published without cross-referencing the build step's own output.
+///
+/// Shells out to `skopeo copy`, the same way [`crate::base_image::pull_and_extract`] does
+/// for the pull direction.
+pub(crate) fn push(
+    image: &str,
+    tag: &str,
+    dest: &str,
+    auth_file: Option<&Path>,
+    digest: &str,
+) -> Result<()> {
+    write::ok(
+        "Pushing",
+        format!("image `{}:{}` ({}) to `{}`", image, tag, digest, dest),
+    )?;
+    let mut skopeo = Command::new("skopeo");
+    skopeo.arg("copy");
+    if let Some(auth_file) = auth_file {
+        skopeo.arg("--authfile").arg(auth_file);
+    }
+    skopeo.arg(format!("oci:{}:{}", image, tag)).arg(dest);
+    let status = skopeo.status().context("Failed to run `skopeo copy`")?;
+    if !status.success() {
+        bail!(
+            "Failed to push image `{}:{}` to `{}` with skopeo",
+            image,
+            tag,
+            dest
+        );
+    }
+    write::ok("Pushed", format!("image `{}` to `{}`", digest, dest))?;
+    Ok(())
+}
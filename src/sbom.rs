@@ -0,0 +1,369 @@
+//! Module for generating software bill of materials (SBOM) documents
+//! describing the RPMs installed into a build's installroot
+//!
+//! Copyright (C) Microsoft Corporation.
+//!
+//! This program is free software: you can redistribute it and/or modify
+//! it under the terms of the GNU General Public License as published by
+//! the Free Software Foundation, either version 3 of the License, or
+//! (at your option) any later version.
+//!
+//! This program is distributed in the hope that it will be useful,
+//! but WITHOUT ANY WARRANTY; without even the implied warranty of
+//! MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//! GNU General Public License for more details.
+//!
+//! You should have received a copy of the GNU General Public License
+//! along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::config::{Config, Repository};
+use crate::lockfile::Lockfile;
+
+/// An installed RPM, as read from the installroot's rpmdb
+#[derive(Debug, Clone)]
+pub(crate) struct InstalledPackage {
+    name: String,
+    evr: String,
+    arch: String,
+    license: String,
+}
+
+/// An SPDX 2.3 package entry
+#[derive(Debug, Serialize)]
+struct SpdxPackage {
+    #[serde(rename = "SPDXID")]
+    spdxid: String,
+    name: String,
+    #[serde(rename = "versionInfo")]
+    version_info: String,
+    #[serde(rename = "downloadLocation")]
+    download_location: String,
+    #[serde(rename = "licenseConcluded")]
+    license_concluded: String,
+    #[serde(rename = "licenseDeclared")]
+    license_declared: String,
+    #[serde(rename = "externalRefs")]
+    external_refs: Vec<SpdxExternalRef>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    checksums: Option<Vec<SpdxChecksum>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    comment: Option<String>,
+}
+
+/// An SPDX 2.3 package checksum entry
+#[derive(Debug, Serialize)]
+struct SpdxChecksum {
+    algorithm: String,
+    #[serde(rename = "checksumValue")]
+    checksum_value: String,
+}
+
+#[derive(Debug, Serialize)]
+struct SpdxExternalRef {
+    #[serde(rename = "referenceCategory")]
+    reference_category: String,
+    #[serde(rename = "referenceType")]
+    reference_type: String,
+    #[serde(rename = "referenceLocator")]
+    reference_locator: String,
+}
+
+#[derive(Debug, Serialize)]
+struct SpdxRelationship {
+    #[serde(rename = "spdxElementId")]
+    spdx_element_id: String,
+    #[serde(rename = "relationshipType")]
+    relationship_type: String,
+    #[serde(rename = "relatedSpdxElement")]
+    related_spdx_element: String,
+}
+
+#[derive(Debug, Serialize)]
+struct SpdxCreationInfo {
+    creators: Vec<String>,
+    created: String,
+}
+
+/// An SPDX 2.3 document
+#[derive(Debug, Serialize)]
+struct SpdxDocument {
+    #[serde(rename = "spdxVersion")]
+    spdx_version: String,
+    #[serde(rename = "dataLicense")]
+    data_license: String,
+    #[serde(rename = "SPDXID")]
+    spdxid: String,
+    name: String,
+    #[serde(rename = "documentNamespace")]
+    document_namespace: String,
+    #[serde(rename = "creationInfo")]
+    creation_info: SpdxCreationInfo,
+    packages: Vec<SpdxPackage>,
+    relationships: Vec<SpdxRelationship>,
+}
+
+/// Read the installed packages from the rpmdb in `installroot`, sorted by NEVRA
+/// so the resulting SBOM is byte-for-byte reproducible across rebuilds.
+fn read_installed_packages(installroot: impl AsRef<Path>) -> Result<Vec<InstalledPackage>> {
+    let output = Command::new("rpm")
+        .arg("--root")
+        .arg(installroot.as_ref())
+        .arg("-qa")
+        .arg("--qf")
+        .arg("%{NAME}\\t%{EPOCH}:%{VERSION}-%{RELEASE}\\t%{ARCH}\\t%{LICENSE}\\n")
+        .output()
+        .context("Failed to run `rpm -qa` against installroot")?;
+    if !output.status.success() {
+        bail!(
+            "rpm -qa failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    let mut packages = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let fields: Vec<&str> = line.split('\t').collect();
+            InstalledPackage {
+                name: fields.first().unwrap_or(&"").to_string(),
+                evr: fields
+                    .get(1)
+                    .unwrap_or(&"")
+                    .trim_start_matches("(none):")
+                    .to_string(),
+                arch: fields.get(2).unwrap_or(&"").to_string(),
+                license: fields.get(3).unwrap_or(&"NOASSERTION").to_string(),
+            }
+        })
+        .collect::<Vec<_>>();
+    packages.sort_by(|a, b| (&a.name, &a.evr, &a.arch).cmp(&(&b.name, &b.evr, &b.arch)));
+    Ok(packages)
+}
+
+fn sanitize_spdxid(nevra: &str) -> String {
+    nevra
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect()
+}
+
+/// Generate an SPDX 2.3 SBOM document describing every RPM installed in `installroot`.
+pub(crate) fn generate_spdx(
+    installroot: impl AsRef<Path>,
+    image: &str,
+    creation_time: DateTime<Utc>,
+) -> Result<Vec<u8>> {
+    let installed = read_installed_packages(installroot)?;
+
+    let mut packages = Vec::with_capacity(installed.len());
+    let mut relationships = Vec::with_capacity(installed.len());
+    for pkg in &installed {
+        let nevra = format!("{}-{}.{}", pkg.name, pkg.evr, pkg.arch);
+        let spdxid = format!("SPDXRef-{}", sanitize_spdxid(&nevra));
+        packages.push(SpdxPackage {
+            spdxid: spdxid.clone(),
+            name: pkg.name.clone(),
+            version_info: pkg.evr.clone(),
+            download_location: "NOASSERTION".to_string(),
+            license_concluded: "NOASSERTION".to_string(),
+            license_declared: pkg.license.clone(),
+            external_refs: vec![SpdxExternalRef {
+                reference_category: "PACKAGE-MANAGER".to_string(),
+                reference_type: "purl".to_string(),
+                reference_locator: format!(
+                    "pkg:rpm/{}@{}?arch={}",
+                    pkg.name, pkg.evr, pkg.arch
+                ),
+            }],
+            checksums: None,
+            comment: None,
+        });
+        relationships.push(SpdxRelationship {
+            spdx_element_id: "SPDXRef-DOCUMENT".to_string(),
+            relationship_type: "CONTAINS".to_string(),
+            related_spdx_element: spdxid,
+        });
+    }
+
+    let document = SpdxDocument {
+        spdx_version: "SPDX-2.3".to_string(),
+        data_license: "CC0-1.0".to_string(),
+        spdxid: "SPDXRef-DOCUMENT".to_string(),
+        name: image.to_string(),
+        document_namespace: format!("https://rpmoci.microsoft.com/spdx/{}", image),
+        creation_info: SpdxCreationInfo {
+            creators: vec!["Tool: rpmoci".to_string()],
+            created: creation_time.to_rfc3339(),
+        },
+        packages,
+        relationships,
+    };
+
+    Ok(serde_json::to_vec_pretty(&document)?)
+}
+
+/// The repository download location to record for `repoid`, falling back to
+/// `NOASSERTION` if the repository isn't (or is no longer) configured.
+fn repo_download_location(repositories: &[Repository], repoid: &str) -> String {
+    repositories
+        .iter()
+        .find(|repo| repo.repo_id() == repoid)
+        .map(|repo| match repo {
+            Repository::Url(url) => url.to_string(),
+            Repository::Definition(def) => def.url.to_string(),
+            Repository::Id(id) => id.clone(),
+        })
+        .unwrap_or_else(|| "NOASSERTION".to_string())
+}
+
+/// A package's NEVRA and build time, paired with the digest of the OCI layer blob that
+/// contains its files. Produced by `imager::Imager::finish` and consumed by
+/// [`generate_layer_spdx`] to build a per-layer SBOM.
+pub(crate) struct LayerSbomEntry {
+    pub(crate) name: String,
+    pub(crate) evr: String,
+    pub(crate) arch: String,
+    pub(crate) buildtime: i64,
+    pub(crate) layer_digest: String,
+}
+
+/// Generate an SPDX 2.3 SBOM mapping every package to the digest of the image layer its
+/// files were written into, for attaching to an image as an OCI referrer artifact (see
+/// `imager::Imager::attach_layer_sbom`).
+pub(crate) fn generate_layer_spdx(
+    entries: &[LayerSbomEntry],
+    image: &str,
+    creation_time: DateTime<Utc>,
+) -> Result<Vec<u8>> {
+    let mut packages = Vec::with_capacity(entries.len());
+    let mut relationships = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let nevra = format!("{}-{}.{}", entry.name, entry.evr, entry.arch);
+        let spdxid = format!("SPDXRef-{}", sanitize_spdxid(&nevra));
+        packages.push(SpdxPackage {
+            spdxid: spdxid.clone(),
+            name: entry.name.clone(),
+            version_info: entry.evr.clone(),
+            download_location: "NOASSERTION".to_string(),
+            license_concluded: "NOASSERTION".to_string(),
+            license_declared: "NOASSERTION".to_string(),
+            external_refs: vec![
+                SpdxExternalRef {
+                    reference_category: "PACKAGE-MANAGER".to_string(),
+                    reference_type: "purl".to_string(),
+                    reference_locator: format!(
+                        "pkg:rpm/{}@{}?arch={}",
+                        entry.name, entry.evr, entry.arch
+                    ),
+                },
+                SpdxExternalRef {
+                    reference_category: "OTHER".to_string(),
+                    reference_type: "layer-digest".to_string(),
+                    reference_locator: entry.layer_digest.clone(),
+                },
+            ],
+            checksums: None,
+            comment: Some(format!("Build time (unix epoch): {}", entry.buildtime)),
+        });
+        relationships.push(SpdxRelationship {
+            spdx_element_id: "SPDXRef-DOCUMENT".to_string(),
+            relationship_type: "CONTAINS".to_string(),
+            related_spdx_element: spdxid,
+        });
+    }
+
+    let document = SpdxDocument {
+        spdx_version: "SPDX-2.3".to_string(),
+        data_license: "CC0-1.0".to_string(),
+        spdxid: "SPDXRef-DOCUMENT".to_string(),
+        name: format!("{} (per-layer)", image),
+        document_namespace: format!("https://rpmoci.microsoft.com/spdx/{}/layers", image),
+        creation_info: SpdxCreationInfo {
+            creators: vec!["Tool: rpmoci".to_string()],
+            created: creation_time.to_rfc3339(),
+        },
+        packages,
+        relationships,
+    };
+
+    Ok(serde_json::to_vec_pretty(&document)?)
+}
+
+/// Generate an SPDX 2.3 SBOM directly from a resolved lock file's package set.
+///
+/// Unlike [`generate_spdx`], this doesn't require an installroot, so it can run as part
+/// of both `build` and `vendor`. Since the lock file doesn't record installed license
+/// tags, each package's checksum, source repository and GPG key provenance are recorded
+/// instead, so downstream tooling can verify supply-chain integrity without reparsing the
+/// lock file's TOML format.
+pub(crate) fn generate_spdx_from_lockfile(
+    lockfile: &Lockfile,
+    cfg: &Config,
+    image: &str,
+    creation_time: DateTime<Utc>,
+) -> Result<Vec<u8>> {
+    let mut packages = Vec::new();
+    let mut relationships = Vec::new();
+    let repo_gpg_config = lockfile.repo_gpg_config();
+
+    for pkg in lockfile.iter_packages() {
+        let nevr = format!("{}-{}", pkg.name, pkg.evr);
+        let spdxid = format!("SPDXRef-{}", sanitize_spdxid(&nevr));
+        let download_location =
+            repo_download_location(&cfg.contents.repositories, pkg.repoid());
+        let comment = repo_gpg_config.get(pkg.repoid()).map(|info| {
+            format!(
+                "gpgcheck={}, {} GPG key(s) configured for repository `{}`",
+                info.gpgcheck,
+                info.keys.len(),
+                pkg.repoid()
+            )
+        });
+        packages.push(SpdxPackage {
+            spdxid: spdxid.clone(),
+            name: pkg.name.clone(),
+            version_info: pkg.evr.clone(),
+            download_location,
+            license_concluded: "NOASSERTION".to_string(),
+            license_declared: "NOASSERTION".to_string(),
+            external_refs: vec![SpdxExternalRef {
+                reference_category: "PACKAGE-MANAGER".to_string(),
+                reference_type: "purl".to_string(),
+                reference_locator: format!("pkg:rpm/{}@{}", pkg.name, pkg.evr),
+            }],
+            checksums: Some(vec![SpdxChecksum {
+                algorithm: pkg.checksum.algorithm.spdx_name().to_string(),
+                checksum_value: pkg.checksum.checksum.clone(),
+            }]),
+            comment,
+        });
+        relationships.push(SpdxRelationship {
+            spdx_element_id: "SPDXRef-DOCUMENT".to_string(),
+            relationship_type: "CONTAINS".to_string(),
+            related_spdx_element: spdxid,
+        });
+    }
+
+    let document = SpdxDocument {
+        spdx_version: "SPDX-2.3".to_string(),
+        data_license: "CC0-1.0".to_string(),
+        spdxid: "SPDXRef-DOCUMENT".to_string(),
+        name: image.to_string(),
+        document_namespace: format!("https://rpmoci.microsoft.com/spdx/{}", image),
+        creation_info: SpdxCreationInfo {
+            creators: vec!["Tool: rpmoci".to_string()],
+            created: creation_time.to_rfc3339(),
+        },
+        packages,
+        relationships,
+    };
+
+    Ok(serde_json::to_vec_pretty(&document)?)
+}
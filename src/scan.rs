@@ -0,0 +1,390 @@
+//! Module for offline vulnerability scanning of resolved package sets
+//!
+//! Copyright (C) Microsoft Corporation.
+//!
+//! This program is free software: you can redistribute it and/or modify
+//! it under the terms of the GNU General Public License as published by
+//! the Free Software Foundation, either version 3 of the License, or
+//! (at your option) any later version.
+//!
+//! This program is distributed in the hope that it will be useful,
+//! but WITHOUT ANY WARRANTY; without even the implied warranty of
+//! MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//! GNU General Public License for more details.
+//!
+//! You should have received a copy of the GNU General Public License
+//! along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use std::cmp::Ordering;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+use crate::lockfile::Lockfile;
+use crate::write;
+
+/// Advisory severity, ordered from least to most severe
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Low severity
+    Low,
+    /// Moderate severity
+    Moderate,
+    /// Important severity
+    Important,
+    /// Critical severity
+    Critical,
+}
+
+impl Severity {
+    fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "low" => Some(Self::Low),
+            "moderate" => Some(Self::Moderate),
+            "important" => Some(Self::Important),
+            "critical" => Some(Self::Critical),
+            _ => None,
+        }
+    }
+}
+
+/// A single package fixed by a security advisory
+#[derive(Debug, Clone)]
+struct FixedPackage {
+    name: String,
+    /// The epoch:version-release that fixes the vulnerability
+    evr: String,
+}
+
+/// A security advisory, as parsed from an `updateinfo.xml`-style document
+#[derive(Debug, Clone)]
+struct Advisory {
+    id: String,
+    severity: Severity,
+    fixed_packages: Vec<FixedPackage>,
+}
+
+/// A finding: an installed package that is older than the fix for some advisory
+#[derive(Debug, Serialize)]
+pub struct Finding {
+    /// The id of the advisory this package is affected by, e.g. an RHSA/CVE id
+    pub advisory_id: String,
+    /// The severity of the advisory
+    pub severity: Severity,
+    /// The installed package name
+    pub package: String,
+    /// The installed package's epoch:version-release
+    pub installed_evr: String,
+    /// The epoch:version-release that fixes the advisory
+    pub fixed_evr: String,
+}
+
+/// Parse every `updateinfo.xml`/`updateinfo.xml.gz`-derived advisory document in `dir`.
+///
+/// This implements a minimal subset of the updateinfo schema: `<update>` elements with
+/// an `<id>`, a `severity` used to classify it, and a `<packages>` list of `<package>`
+/// elements carrying `name`/`epoch`/`version`/`release` attributes.
+pub(crate) fn load_advisories(dir: impl AsRef<Path>) -> Result<Vec<Advisory>> {
+    let mut advisories = Vec::new();
+    for entry in fs::read_dir(dir.as_ref())
+        .with_context(|| format!("Failed to read advisories directory `{}`", dir.as_ref().display()))?
+    {
+        let path = entry?.path();
+        if !path.is_file() {
+            continue;
+        }
+        let contents = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read advisory file `{}`", path.display()))?;
+        advisories.extend(parse_updateinfo(&contents));
+    }
+    Ok(advisories)
+}
+
+fn parse_updateinfo(xml: &str) -> Vec<Advisory> {
+    let mut advisories = Vec::new();
+    for update_block in split_tag(xml, "update") {
+        let id = extract_tag_text(update_block, "id").unwrap_or_default();
+        if id.is_empty() {
+            continue;
+        }
+        let severity = extract_attr(update_block, "update", "severity")
+            .or_else(|| extract_tag_text(update_block, "severity"))
+            .and_then(|s| Severity::parse(&s))
+            .unwrap_or(Severity::Low);
+
+        let mut fixed_packages = Vec::new();
+        for package_block in split_self_closing(update_block, "package") {
+            let name = extract_attr_value(package_block, "name");
+            let epoch = extract_attr_value(package_block, "epoch");
+            let version = extract_attr_value(package_block, "version");
+            let release = extract_attr_value(package_block, "release");
+            if let (Some(name), Some(version), Some(release)) = (name, version, release) {
+                let epoch = epoch.filter(|e| !e.is_empty() && e != "0");
+                let evr = match epoch {
+                    Some(epoch) => format!("{}:{}-{}", epoch, version, release),
+                    None => format!("{}-{}", version, release),
+                };
+                fixed_packages.push(FixedPackage { name, evr });
+            }
+        }
+
+        advisories.push(Advisory {
+            id,
+            severity,
+            fixed_packages,
+        });
+    }
+    advisories
+}
+
+/// Split `xml` on top-level `<tag ...> ... </tag>` blocks, returning their full contents
+/// (including the opening/closing tags)
+fn split_tag<'a>(xml: &'a str, tag: &str) -> Vec<&'a str> {
+    let open = format!("<{}", tag);
+    let close = format!("</{}>", tag);
+    let mut blocks = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find(&open) {
+        let Some(end_rel) = rest[start..].find(&close) else {
+            break;
+        };
+        let end = start + end_rel + close.len();
+        blocks.push(&rest[start..end]);
+        rest = &rest[end..];
+    }
+    blocks
+}
+
+/// Find self-closing `<tag .../>` elements within `xml`
+fn split_self_closing<'a>(xml: &'a str, tag: &str) -> Vec<&'a str> {
+    let open = format!("<{}", tag);
+    let mut blocks = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find(&open) {
+        let Some(end_rel) = rest[start..].find('>') else {
+            break;
+        };
+        let end = start + end_rel + 1;
+        blocks.push(&rest[start..end]);
+        rest = &rest[end..];
+    }
+    blocks
+}
+
+fn extract_tag_text(xml: &str, tag: &str) -> Option<String> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    let start = xml.find(&open)? + open.len();
+    let end = xml[start..].find(&close)? + start;
+    Some(xml[start..end].trim().to_string())
+}
+
+fn extract_attr(xml: &str, tag: &str, attr: &str) -> Option<String> {
+    let open = format!("<{}", tag);
+    let start = xml.find(&open)?;
+    let tag_end = xml[start..].find('>')? + start;
+    extract_attr_value(&xml[start..tag_end], attr)
+}
+
+fn extract_attr_value(xml: &str, attr: &str) -> Option<String> {
+    let needle = format!("{}=\"", attr);
+    let start = xml.find(&needle)? + needle.len();
+    let end = xml[start..].find('"')? + start;
+    Some(xml[start..end].to_string())
+}
+
+/// Compare two epoch:version-release strings following `rpmvercmp` semantics:
+/// epoch compares numerically first, then version and release are compared
+/// segment-by-segment, alternating between numeric and alphabetic runs.
+pub(crate) fn evr_cmp(a: &str, b: &str) -> Ordering {
+    let (a_epoch, a_rest) = split_epoch(a);
+    let (b_epoch, b_rest) = split_epoch(b);
+    match a_epoch.cmp(&b_epoch) {
+        Ordering::Equal => {}
+        other => return other,
+    }
+
+    let (a_version, a_release) = a_rest.split_once('-').unwrap_or((a_rest, ""));
+    let (b_version, b_release) = b_rest.split_once('-').unwrap_or((b_rest, ""));
+
+    match rpmvercmp(a_version, b_version) {
+        Ordering::Equal => rpmvercmp(a_release, b_release),
+        other => other,
+    }
+}
+
+fn split_epoch(evr: &str) -> (u64, &str) {
+    match evr.split_once(':') {
+        Some((epoch, rest)) => (epoch.parse().unwrap_or(0), rest),
+        None => (0, evr),
+    }
+}
+
+/// Compare two version (or release) strings the way RPM's `rpmvercmp` does:
+/// walk both strings comparing alternating runs of digits and non-digits,
+/// numeric runs compare numerically, alphabetic runs compare lexically,
+/// and a numeric segment always beats an alphabetic one. `~` sorts before
+/// everything else, even the end of the string (so `1.0~rc1` is older than
+/// `1.0`); `^` sorts before everything else except the end of the string (so
+/// `1.0^` is newer than `1.0` but older than `1.0.1`).
+fn rpmvercmp(a: &str, b: &str) -> Ordering {
+    let mut a = a;
+    let mut b = b;
+
+    loop {
+        a = a.trim_start_matches(|c: char| !c.is_ascii_alphanumeric() && c != '~' && c != '^');
+        b = b.trim_start_matches(|c: char| !c.is_ascii_alphanumeric() && c != '~' && c != '^');
+
+        if a.starts_with('~') || b.starts_with('~') {
+            if !a.starts_with('~') {
+                return Ordering::Greater;
+            }
+            if !b.starts_with('~') {
+                return Ordering::Less;
+            }
+            a = &a[1..];
+            b = &b[1..];
+            continue;
+        }
+
+        if a.starts_with('^') || b.starts_with('^') {
+            if a.is_empty() {
+                return Ordering::Less;
+            }
+            if b.is_empty() {
+                return Ordering::Greater;
+            }
+            if !a.starts_with('^') {
+                return Ordering::Greater;
+            }
+            if !b.starts_with('^') {
+                return Ordering::Less;
+            }
+            a = &a[1..];
+            b = &b[1..];
+            continue;
+        }
+
+        if a.is_empty() && b.is_empty() {
+            return Ordering::Equal;
+        }
+        if a.is_empty() {
+            return Ordering::Less;
+        }
+        if b.is_empty() {
+            return Ordering::Greater;
+        }
+
+        let a_numeric = a.starts_with(|c: char| c.is_ascii_digit());
+        let b_numeric = b.starts_with(|c: char| c.is_ascii_digit());
+
+        let (a_seg, a_remainder) = take_run(a, a_numeric);
+        let (b_seg, b_remainder) = take_run(b, b_numeric);
+
+        let cmp = if a_numeric && b_numeric {
+            let a_trimmed = a_seg.trim_start_matches('0');
+            let b_trimmed = b_seg.trim_start_matches('0');
+            a_trimmed
+                .len()
+                .cmp(&b_trimmed.len())
+                .then_with(|| a_trimmed.cmp(b_trimmed))
+        } else if a_numeric != b_numeric {
+            // a numeric segment always compares greater than an alphabetic one
+            if a_numeric {
+                Ordering::Greater
+            } else {
+                Ordering::Less
+            }
+        } else {
+            a_seg.cmp(b_seg)
+        };
+
+        if cmp != Ordering::Equal {
+            return cmp;
+        }
+
+        a = a_remainder;
+        b = b_remainder;
+    }
+}
+
+fn take_run(s: &str, numeric: bool) -> (&str, &str) {
+    let end = s
+        .find(|c: char| c.is_ascii_digit() != numeric || !c.is_ascii_alphanumeric())
+        .unwrap_or(s.len());
+    s.split_at(end)
+}
+
+/// Compare `lockfile`'s resolved packages against `advisories`, returning every
+/// finding where an installed package is older than the advisory's fixed version.
+pub(crate) fn scan(lockfile: &Lockfile, advisories: &[Advisory]) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for pkg in lockfile.iter_packages() {
+        for advisory in advisories {
+            for fixed in &advisory.fixed_packages {
+                if fixed.name == pkg.name && evr_cmp(&pkg.evr, &fixed.evr) == Ordering::Less {
+                    findings.push(Finding {
+                        advisory_id: advisory.id.clone(),
+                        severity: advisory.severity,
+                        package: pkg.name.clone(),
+                        installed_evr: pkg.evr.clone(),
+                        fixed_evr: fixed.evr.clone(),
+                    });
+                }
+            }
+        }
+    }
+    findings.sort_by(|a, b| (&a.package, &a.advisory_id).cmp(&(&b.package, &b.advisory_id)));
+    findings
+}
+
+/// Print findings as human-readable lines grouped by severity, most severe first.
+pub(crate) fn print_findings(findings: &[Finding]) -> Result<()> {
+    let mut sorted = findings.iter().collect::<Vec<_>>();
+    sorted.sort_by(|a, b| b.severity.cmp(&a.severity));
+    for finding in sorted {
+        write::error(
+            match finding.severity {
+                Severity::Critical => "Critical",
+                Severity::Important => "Important",
+                Severity::Moderate => "Moderate",
+                Severity::Low => "Low",
+            },
+            format!(
+                "{} affects {} {} (fixed in {})",
+                finding.advisory_id, finding.package, finding.installed_evr, finding.fixed_evr
+            ),
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tilde_sorts_before_its_parent_version() {
+        assert_eq!(rpmvercmp("1.0~rc1", "1.0"), Ordering::Less);
+        assert_eq!(rpmvercmp("1.0", "1.0~rc1"), Ordering::Greater);
+        assert_eq!(rpmvercmp("1.0~rc1", "1.0~rc2"), Ordering::Less);
+        assert_eq!(rpmvercmp("1.0~rc1", "1.0~rc1"), Ordering::Equal);
+    }
+
+    #[test]
+    fn caret_sorts_after_its_parent_version_but_before_the_next_one() {
+        assert_eq!(rpmvercmp("1.0^", "1.0"), Ordering::Greater);
+        assert_eq!(rpmvercmp("1.0^git1", "1.0.1"), Ordering::Less);
+        assert_eq!(rpmvercmp("1.0^git1", "1.0^git2"), Ordering::Less);
+    }
+
+    #[test]
+    fn plain_versions_still_compare_as_before() {
+        assert_eq!(rpmvercmp("1.0", "1.0"), Ordering::Equal);
+        assert_eq!(rpmvercmp("1.1", "1.0"), Ordering::Greater);
+        assert_eq!(rpmvercmp("1.0", "1.0.1"), Ordering::Less);
+        assert_eq!(rpmvercmp("1.a", "1.1"), Ordering::Less);
+    }
+}
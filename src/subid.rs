@@ -46,6 +46,7 @@ pub fn setup_id_maps(child: Pid, uid: Uid, gid: Gid) -> anyhow::Result<()> {
         &uid_string,
         username.as_deref(),
         child,
+        false,
     )
     .context("Failed to read subuids from /etc/subuid")?;
 
@@ -59,6 +60,7 @@ pub fn setup_id_maps(child: Pid, uid: Uid, gid: Gid) -> anyhow::Result<()> {
         &gid_string,
         groupname.as_deref(),
         child,
+        true,
     )
     .context("Failed to read subgids from /etc/subgid")?;
 
@@ -98,6 +100,7 @@ fn newidmap_args(
     id: &str,
     name: Option<&str>,
     child: Pid,
+    is_group: bool,
 ) -> Result<(Vec<String>, usize)> {
     let mut args = vec![
         child.to_string(),
@@ -107,7 +110,7 @@ fn newidmap_args(
     ];
 
     let mut next_id = 1;
-    for range in get_sub_id_ranges(etc_subid, id, name)? {
+    for range in get_sub_id_ranges(etc_subid, id, name, is_group)? {
         args.push(next_id.to_string());
         args.push(range.start.to_string());
         args.push(range.count.to_string());
@@ -116,13 +119,21 @@ fn newidmap_args(
     Ok((args, next_id))
 }
 
-/// Get the subid ranges for a user or group
+/// Get the subid ranges for a user or group.
+///
+/// Reads the flat `subid_file` (`/etc/subuid`/`/etc/subgid`) first. On hosts where
+/// subordinate ID ranges are served by SSSD/LDAP or another NSS backend rather than
+/// being written to those files, the flat file has nothing for `name`/`id`, so falls
+/// back to asking the system name service via shadow-utils' `getsubids` (`getsubids -g`
+/// for groups), mirroring how `sysinfo` resolves users through the name service rather
+/// than assuming a local file.
 fn get_sub_id_ranges(
     subid_file: impl Read,
     id: &str,
     name: Option<&str>,
+    is_group: bool,
 ) -> io::Result<Vec<SubIdRange>> {
-    Ok(read_to_string(subid_file)?
+    let ranges = read_to_string(subid_file)?
         .lines() // split the string into an iterator of string slices
         .filter_map(|line| {
             let parts = line.splitn(3, ':').collect::<Vec<_>>();
@@ -140,7 +151,53 @@ fn get_sub_id_ranges(
                 None
             }
         })
-        .collect())
+        .collect::<Vec<_>>();
+
+    if !ranges.is_empty() {
+        return Ok(ranges);
+    }
+
+    match name {
+        Some(name) => Ok(getsubids_ranges(name, is_group)),
+        // getsubids looks up by name only; with no name to query we have nothing left to try.
+        None => Ok(ranges),
+    }
+}
+
+/// Ask `getsubids`/`getsubids -g` for `name`'s subordinate id ranges, parsing lines of
+/// the form `index: owner start count` (e.g. `0: jdoe 100000 65536`) and collecting the
+/// `start`/`count` columns while ignoring the index and owner. A non-zero exit (e.g.
+/// `name` isn't known to the configured NSS backend) or a missing `getsubids` binary is
+/// treated as "no ranges" rather than an error.
+fn getsubids_ranges(name: &str, is_group: bool) -> Vec<SubIdRange> {
+    let mut cmd = Command::new("getsubids");
+    if is_group {
+        cmd.arg("-g");
+    }
+    let Ok(output) = cmd.arg(name).output() else {
+        return Vec::new();
+    };
+    if !output.status.success() {
+        return Vec::new();
+    }
+    parse_getsubids_output(&output.stdout)
+}
+
+/// Parse `getsubids` output: one `index: owner start count` line per range.
+fn parse_getsubids_output(stdout: &[u8]) -> Vec<SubIdRange> {
+    String::from_utf8_lossy(stdout)
+        .lines()
+        .filter_map(|line| {
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            let [_index, _owner, start, count] = fields[..] else {
+                return None;
+            };
+            match (start.parse::<usize>(), count.parse::<usize>()) {
+                (Ok(start), Ok(count)) => Some(SubIdRange { start, count }),
+                _ => None,
+            }
+        })
+        .collect()
 }
 
 #[cfg(test)]
@@ -148,7 +205,7 @@ mod tests {
 
     use nix::unistd::Pid;
 
-    use super::{get_sub_id_ranges, SubIdRange};
+    use super::{get_sub_id_ranges, parse_getsubids_output, SubIdRange};
 
     #[test]
     fn test_get_sub_id_ranges() {
@@ -161,14 +218,14 @@ user1:1:8
 1000:100000:5
         "#;
         assert_eq!(
-            get_sub_id_ranges(subid_contents.as_bytes(), "1000", None).unwrap(),
+            get_sub_id_ranges(subid_contents.as_bytes(), "1000", None, false).unwrap(),
             vec![SubIdRange {
                 start: 100000,
                 count: 5
             }]
         );
         assert_eq!(
-            get_sub_id_ranges(subid_contents.as_bytes(), "1000", Some("user1")).unwrap(),
+            get_sub_id_ranges(subid_contents.as_bytes(), "1000", Some("user1"), false).unwrap(),
             vec![
                 SubIdRange {
                     start: 100,
@@ -182,7 +239,7 @@ user1:1:8
             ]
         );
         assert_eq!(
-            get_sub_id_ranges(subid_contents.as_bytes(), "1001", Some("user1")).unwrap(),
+            get_sub_id_ranges(subid_contents.as_bytes(), "1001", Some("user1"), false).unwrap(),
             vec![
                 SubIdRange {
                     start: 100,
@@ -192,7 +249,7 @@ user1:1:8
             ]
         );
         assert_eq!(
-            get_sub_id_ranges(subid_contents.as_bytes(), "1001", Some("user2")).unwrap(),
+            get_sub_id_ranges(subid_contents.as_bytes(), "1001", Some("user2"), false).unwrap(),
             vec![SubIdRange {
                 start: 10,
                 count: 10
@@ -200,6 +257,26 @@ user1:1:8
         );
     }
 
+    #[test]
+    fn test_parse_getsubids_output() {
+        let stdout = b"0: jdoe 100000 65536\n1: jdoe 200000 1000\n";
+        assert_eq!(
+            parse_getsubids_output(stdout),
+            vec![
+                SubIdRange {
+                    start: 100000,
+                    count: 65536
+                },
+                SubIdRange {
+                    start: 200000,
+                    count: 1000
+                }
+            ]
+        );
+        assert_eq!(parse_getsubids_output(b""), Vec::new());
+        assert_eq!(parse_getsubids_output(b"not a valid line"), Vec::new());
+    }
+
     #[test]
     fn test_newidmap_args() {
         let subid_contents = r#"
@@ -215,7 +292,8 @@ user1:1:8
                 subid_contents.as_bytes(),
                 "1000",
                 Some("user1"),
-                Pid::from_raw(1234)
+                Pid::from_raw(1234),
+                false
             )
             .unwrap(),
             (
@@ -0,0 +1,160 @@
+//! Variable substitution (`${VAR}`) for string-valued config fields
+//!
+//! Copyright (C) Microsoft Corporation.
+//!
+//! This program is free software: you can redistribute it and/or modify
+//! it under the terms of the GNU General Public License as published by
+//! the Free Software Foundation, either version 3 of the License, or
+//! (at your option) any later version.
+//!
+//! This program is distributed in the hope that it will be useful,
+//! but WITHOUT ANY WARRANTY; without even the implied warranty of
+//! MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//! GNU General Public License for more details.
+//!
+//! You should have received a copy of the GNU General Public License
+//! along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use std::collections::HashMap;
+
+use anyhow::{bail, Result};
+
+/// Dotted paths (relative to the config root) of fields whose string values are
+/// passed through [`expand_string`] when a config is loaded. Other fields (e.g.
+/// package specs) are left alone, so a literal `$` in those never needs escaping.
+pub(crate) const SUBSTITUTED_PATHS: &[&str] = &[
+    "contents.repositories",
+    "image.envs",
+    "image.labels",
+    "image.entrypoint",
+    "image.cmd",
+    "image.workingdir",
+];
+
+/// The rpmoci-provided builtin variables available to [`expand_string`], before the
+/// process environment is layered on top (see [`substitution_vars`]).
+fn builtin_vars() -> HashMap<String, String> {
+    let mut vars = HashMap::new();
+    vars.insert("ARCH".to_string(), crate::config::host_oci_arch().to_string());
+    vars.insert("TIMESTAMP".to_string(), build_timestamp());
+    vars
+}
+
+/// The `${TIMESTAMP}` builtin: `SOURCE_DATE_EPOCH` if set, so templated fields agree
+/// with the timestamp embedded in the image itself (see
+/// `lockfile::build::creation_time`), otherwise the current time, both as a Unix
+/// timestamp.
+fn build_timestamp() -> String {
+    std::env::var("SOURCE_DATE_EPOCH")
+        .unwrap_or_else(|_| chrono::Utc::now().timestamp().to_string())
+}
+
+/// The full variable namespace [`expand_string`] resolves `${VAR}` references
+/// against: rpmoci's builtins (see [`builtin_vars`]), overridden by the process
+/// environment so e.g. `ARCH` can still be forced by the caller if ever needed.
+pub(crate) fn substitution_vars() -> HashMap<String, String> {
+    let mut vars = builtin_vars();
+    vars.extend(std::env::vars());
+    vars
+}
+
+/// Expand `${VAR}` / `${VAR:-default}` placeholders in `s` against `vars`. A
+/// variable with no entry in `vars` and no `:-default` is an error. A literal `$` is
+/// written as `$$`; any other lone `$` (not starting `$$` or `${`) is passed through
+/// unchanged.
+pub(crate) fn expand_string(s: &str, vars: &HashMap<String, String>) -> Result<String> {
+    let mut out = String::with_capacity(s.len());
+    let mut i = 0;
+    while i < s.len() {
+        if !s[i..].starts_with('$') {
+            let ch = s[i..].chars().next().unwrap();
+            out.push(ch);
+            i += ch.len_utf8();
+            continue;
+        }
+        if s[i..].starts_with("$$") {
+            out.push('$');
+            i += 2;
+            continue;
+        }
+        if s[i..].starts_with("${") {
+            let Some(end) = s[i..].find('}') else {
+                bail!("Unterminated `${{` in `{}` (missing closing `}}`)", s);
+            };
+            let inner = &s[i + 2..i + end];
+            let (name, default) = match inner.split_once(":-") {
+                Some((name, default)) => (name, Some(default)),
+                None => (inner, None),
+            };
+            match vars.get(name).map(String::as_str).or(default) {
+                Some(value) => out.push_str(value),
+                None => bail!(
+                    "Undefined variable `${{{name}}}` in config; set it in the environment \
+                     or give a default with `${{{name}:-default}}`"
+                ),
+            }
+            i += end + 1;
+            continue;
+        }
+        out.push('$');
+        i += 1;
+    }
+    Ok(out)
+}
+
+/// Recursively expand every string leaf of `value` (a TOML value, e.g. one of the
+/// subtrees named in [`SUBSTITUTED_PATHS`]) against `vars`.
+pub(crate) fn substitute_value(
+    value: toml::Value,
+    vars: &HashMap<String, String>,
+) -> Result<toml::Value> {
+    match value {
+        toml::Value::String(s) => Ok(toml::Value::String(expand_string(&s, vars)?)),
+        toml::Value::Array(arr) => Ok(toml::Value::Array(
+            arr.into_iter()
+                .map(|v| substitute_value(v, vars))
+                .collect::<Result<_>>()?,
+        )),
+        toml::Value::Table(table) => Ok(toml::Value::Table(
+            table
+                .into_iter()
+                .map(|(k, v)| Ok((k, substitute_value(v, vars)?)))
+                .collect::<Result<_>>()?,
+        )),
+        other => Ok(other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expands_known_variable() {
+        let vars = HashMap::from([("FOO".to_string(), "bar".to_string())]);
+        assert_eq!(expand_string("pre-${FOO}-post", &vars).unwrap(), "pre-bar-post");
+    }
+
+    #[test]
+    fn falls_back_to_default_when_undefined() {
+        let vars = HashMap::new();
+        assert_eq!(expand_string("${FOO:-baz}", &vars).unwrap(), "baz");
+    }
+
+    #[test]
+    fn errors_on_undefined_variable_without_default() {
+        let vars = HashMap::new();
+        assert!(expand_string("${FOO}", &vars).is_err());
+    }
+
+    #[test]
+    fn double_dollar_is_a_literal_escape() {
+        let vars = HashMap::new();
+        assert_eq!(expand_string("price: $$5", &vars).unwrap(), "price: $5");
+    }
+
+    #[test]
+    fn defined_variable_wins_over_its_own_default() {
+        let vars = HashMap::from([("FOO".to_string(), "bar".to_string())]);
+        assert_eq!(expand_string("${FOO:-baz}", &vars).unwrap(), "bar");
+    }
+}
@@ -0,0 +1,162 @@
+//! Module for `rpmoci verify`: rebuild a lock file into a fresh OCI layout and check
+//! that the resulting manifest digest is reproducible
+//!
+//! Copyright (C) Microsoft Corporation.
+//!
+//! This program is free software: you can redistribute it and/or modify
+//! it under the terms of the GNU General Public License as published by
+//! the Free Software Foundation, either version 3 of the License, or
+//! (at your option) any later version.
+//!
+//! This program is distributed in the hope that it will be useful,
+//! but WITHOUT ANY WARRANTY; without even the implied warranty of
+//! MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//! GNU General Public License for more details.
+//!
+//! You should have received a copy of the GNU General Public License
+//! along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use oci_spec::image::{ImageIndex, ImageManifest};
+use tempfile::TempDir;
+
+use crate::config::Config;
+use crate::lockfile::Lockfile;
+use crate::write;
+
+/// Rebuild `lockfile` for `cfg` into a fresh temporary OCI layout tagged `tag` and check
+/// that the resulting manifest digest is reproducible.
+///
+/// If `against` is given, the rebuilt digest is compared against the manifest tagged
+/// `tag` in that existing OCI layout. Otherwise it falls back to `expected_digest`
+/// (typically recorded via the `expected_digest` config option). On mismatch an error
+/// is returned; when `against` is given, the layer digests of both manifests are
+/// diffed first.
+pub(crate) fn verify(
+    lockfile: &Lockfile,
+    cfg: &Config,
+    vendor_dir: Option<&Path>,
+    tag: &str,
+    against: Option<&Path>,
+    expected_digest: Option<&str>,
+) -> Result<()> {
+    let (expected_path, expected_digest) = if let Some(against) = against {
+        (Some(against), manifest_digest(against, tag)?)
+    } else if let Some(expected_digest) = expected_digest {
+        (None, expected_digest.to_string())
+    } else {
+        bail!(
+            "`verify` requires either `--against <oci-layout>` or an `expected_digest` \
+             recorded in the rpmoci manifest"
+        );
+    };
+
+    let scratch = TempDir::new().context("Failed to create scratch directory for verify")?;
+    let image = scratch.path().join("image");
+    let image_str = image
+        .to_str()
+        .context("scratch verify path is not valid UTF-8")?;
+
+    write::ok("Verifying", format!("reproducibility of `{}`", tag))?;
+    lockfile.build(
+        cfg,
+        image_str,
+        tag,
+        vendor_dir,
+        Default::default(),
+        None,
+        None,
+        None,
+        false,
+        None,
+        false,
+        Default::default(),
+        Default::default(),
+        false,
+    )?;
+
+    let rebuilt_digest = manifest_digest(&image, tag)?;
+    if rebuilt_digest == expected_digest {
+        write::ok(
+            "Verified",
+            format!("`{}` is reproducible ({})", tag, rebuilt_digest),
+        )?;
+        return Ok(());
+    }
+
+    write::error(
+        "Mismatch",
+        format!(
+            "rebuilt digest `{}` does not match expected digest `{}`",
+            rebuilt_digest, expected_digest
+        ),
+    )?;
+    if let Some(expected_path) = expected_path {
+        let rebuilt_manifest = read_tagged_manifest(&image, tag)?;
+        let expected_manifest = read_tagged_manifest(expected_path, tag)?;
+        diff_layers(&rebuilt_manifest, &expected_manifest)?;
+    }
+    bail!("image `{}` is not reproducible", tag);
+}
+
+/// Find the descriptor in `layout`'s `index.json` tagged `tag`
+fn tagged_descriptor(layout: &Path, tag: &str) -> Result<oci_spec::image::Descriptor> {
+    let index_path = layout.join("index.json");
+    let index = ImageIndex::from_file(&index_path)
+        .with_context(|| format!("Failed to read `{}`", index_path.display()))?;
+    index
+        .manifests()
+        .iter()
+        .find(|m| {
+            m.annotations()
+                .as_ref()
+                .and_then(|a| a.get("org.opencontainers.image.ref.name"))
+                .is_some_and(|t| t == tag)
+        })
+        .cloned()
+        .with_context(|| format!("No manifest tagged `{}` found in `{}`", tag, layout.display()))
+}
+
+fn manifest_digest(layout: &Path, tag: &str) -> Result<String> {
+    Ok(tagged_descriptor(layout, tag)?.digest().to_string())
+}
+
+fn read_tagged_manifest(layout: &Path, tag: &str) -> Result<ImageManifest> {
+    let digest = manifest_digest(layout, tag)?;
+    let (algorithm, hex) = digest
+        .split_once(':')
+        .with_context(|| format!("Unexpected digest format `{}`", digest))?;
+    let manifest_path = layout.join("blobs").join(algorithm).join(hex);
+    ImageManifest::from_file(&manifest_path)
+        .with_context(|| format!("Failed to read `{}`", manifest_path.display()))
+}
+
+fn diff_layers(rebuilt: &ImageManifest, expected: &ImageManifest) -> Result<()> {
+    let rebuilt_layers = rebuilt.layers();
+    let expected_layers = expected.layers();
+    for (i, (a, b)) in rebuilt_layers.iter().zip(expected_layers.iter()).enumerate() {
+        if a.digest() != b.digest() {
+            write::error(
+                "Diverged",
+                format!(
+                    "layer {} digest `{}` != expected `{}`",
+                    i,
+                    a.digest(),
+                    b.digest()
+                ),
+            )?;
+        }
+    }
+    if rebuilt_layers.len() != expected_layers.len() {
+        write::error(
+            "Diverged",
+            format!(
+                "rebuilt image has {} layer(s), expected has {}",
+                rebuilt_layers.len(),
+                expected_layers.len()
+            ),
+        )?;
+    }
+    Ok(())
+}